@@ -0,0 +1,54 @@
+use crate::aabb::Aabb;
+use crate::hittable::{hit_skipping, HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::rtweekend::*;
+use crate::texture::Texture;
+use std::sync::Arc;
+
+// Cuts holes in any object with an opacity texture, for leaves, fences and the like modeled
+// as simple shapes. The first channel of `alpha` is the opacity at each hit: 0 is a hole, 1 is
+// solid, and values in between let that fraction of rays through at random.
+//
+// Hits in a hole are skipped and the search resumes past them, so rays, shadow rays included,
+// go on to whatever lies behind, including the far side of the same object.
+pub struct AlphaMask {
+    object: Arc<dyn Hittable>,
+    alpha: Arc<dyn Texture>,
+}
+
+impl AlphaMask {
+    pub fn new(object: Arc<dyn Hittable>, alpha: Arc<dyn Texture>) -> Self {
+        Self { object, alpha }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord, sampler: &mut Sampler) -> bool {
+        let alpha = self.alpha.value(rec.u, rec.v, &rec.p).x();
+        alpha >= 1.0 || (alpha > 0.0 && sampler.next_f64() < alpha)
+    }
+}
+
+impl Hittable for AlphaMask {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        hit_skipping(self.object.as_ref(), r, ray_t, rec, sampler, |rec, sampler| !self.is_opaque(rec, sampler))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
+    }
+
+    fn surface_area(&self) -> f64 {
+        self.object.surface_area()
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        self.object.normal_cone()
+    }
+}
@@ -0,0 +1,160 @@
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::onb::Onb;
+use crate::rtweekend::*;
+use crate::texture::Texture;
+use std::sync::Arc;
+
+// Step in surface coordinates for the finite differences of a bump map
+const BUMP_DELTA: f64 = 5e-4;
+
+// Smallest cosine allowed between the outgoing direction and the shading normal
+const MIN_SHADING_COS: f64 = 0.05;
+
+enum Perturbation {
+    // Tangent-space normal encoded as color, with each channel in [0,1] mapping to [-1,1]:
+    // red along dp/du, green along dp/dv and blue along the surface normal
+    NormalMap(Arc<dyn Texture>),
+    // Height field read from the first channel of a texture. The normal is bent as if the
+    // surface were displaced along it by `scale` times the height.
+    Bump { height: Arc<dyn Texture>, scale: f64 },
+}
+
+// Wraps a material to shade it with a perturbed normal, adding surface detail without extra
+// geometry. The geometry itself stays put: rays still hit and leave the original surface, and
+// only the frame the base material shades in changes.
+//
+// The perturbed normal is bent back towards the geometric one when it would face away from the
+// viewer, and light is only allowed through in the directions the true surface agrees with, so
+// reflection never leaks through a surface nor transmission appears in front of it.
+pub struct Bumped {
+    pub base: Arc<dyn Material>,
+    perturbation: Perturbation,
+}
+
+impl Bumped {
+    pub fn normal_map(base: Arc<dyn Material>, normals: Arc<dyn Texture>) -> Self {
+        Self { base, perturbation: Perturbation::NormalMap(normals) }
+    }
+
+    pub fn bump_map(base: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { base, perturbation: Perturbation::Bump { height, scale } }
+    }
+
+    // Unit shading normal at `rec`, outward from the surface
+    fn outward_shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = if rec.front_face { rec.geometric_normal } else { -rec.geometric_normal };
+        let perturbed = match &self.perturbation {
+            Perturbation::NormalMap(normals) => {
+                let c = 2.0 * normals.value(rec.u, rec.v, &rec.p) - Color::new(1.0, 1.0, 1.0);
+                let (t, b) = tangent_frame(rec, &n);
+                c.x() * t + c.y() * b + c.z() * n
+            }
+            Perturbation::Bump { height, scale } => {
+                let d = |u: f64, v: f64, p: Point3| scale * height.value(u, v, &p).x();
+                let displace = d(rec.u, rec.v, rec.p);
+                let du = (d(rec.u + BUMP_DELTA, rec.v, rec.p + BUMP_DELTA * rec.dpdu) - displace) / BUMP_DELTA;
+                let dv = (d(rec.u, rec.v + BUMP_DELTA, rec.p + BUMP_DELTA * rec.dpdv) - displace) / BUMP_DELTA;
+                let (t, b) = tangent_frame(rec, &n);
+                let dpdu = if rec.dpdu.near_zero() { t } else { rec.dpdu };
+                let dpdv = if rec.dpdv.near_zero() { b } else { rec.dpdv };
+                (dpdu + du * n).cross(&(dpdv + dv * n))
+            }
+        };
+
+        if perturbed.near_zero() {
+            return n;
+        }
+        let perturbed = perturbed.unit_vector();
+        // Parameterizations with a left-handed frame give normals on the wrong side
+        if perturbed.dot(&n) < 0.0 { -perturbed } else { perturbed }
+    }
+
+    // Copy of `rec` whose normal is the shading normal seen from `r_in`
+    fn shading_record(&self, r_in: &Ray, rec: &HitRecord) -> HitRecord {
+        let mut shading = rec.clone();
+        if rec.geometric_normal.near_zero() {
+            return shading;
+        }
+        let ng = rec.geometric_normal;
+        let mut ns = self.outward_shading_normal(rec);
+        if !rec.front_face {
+            ns = -ns;
+        }
+
+        // Tilt the normal towards the geometric one until the viewer is above its hemisphere
+        let wo = -r_in.direction().unit_vector();
+        let (cos_s, cos_g) = (wo.dot(&ns), wo.dot(&ng));
+        if cos_s < MIN_SHADING_COS && cos_g > 0.0 {
+            ns = (ns + ((MIN_SHADING_COS - cos_s) / cos_g) * ng).unit_vector();
+        }
+
+        shading.normal = ns;
+        shading
+    }
+}
+
+// Unit tangent and bitangent perpendicular to `n`, following dp/du and dp/dv where they exist
+fn tangent_frame(rec: &HitRecord, n: &Vec3) -> (Vec3, Vec3) {
+    let frame = Onb::from_normal_tangent(n, &rec.dpdu);
+    let t = frame.u();
+    let b = rec.dpdv - rec.dpdv.dot(n) * *n - rec.dpdv.dot(&t) * t;
+    if b.near_zero() { (t, n.cross(&t)) } else { (t, b.unit_vector()) }
+}
+
+// Whether the shading and geometric normals agree on `wi` being a reflection or a transmission
+fn consistent(r_in: &Ray, rec: &HitRecord, shading: &HitRecord, wi: &Vec3) -> bool {
+    if rec.geometric_normal.near_zero() {
+        return true;
+    }
+    let wo = -r_in.direction();
+    let geometric = wo.dot(&rec.geometric_normal) * wi.dot(&rec.geometric_normal) > 0.0;
+    let shaded = wo.dot(&shading.normal) * wi.dot(&shading.normal) > 0.0;
+    geometric == shaded
+}
+
+impl Material for Bumped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        match self.sample(r_in, rec, sampler) {
+            Some(srec) => {
+                *attenuation = srec.attenuation;
+                *scattered = srec.scattered;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let shading = self.shading_record(r_in, rec);
+        let srec = self.base.sample(r_in, &shading, sampler)?;
+        if !consistent(r_in, rec, &shading, &srec.scattered.direction()) {
+            return None;
+        }
+        Some(srec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let shading = self.shading_record(r_in, rec);
+        if !consistent(r_in, rec, &shading, wi) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.base.eval(r_in, &shading, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let shading = self.shading_record(r_in, rec);
+        if !consistent(r_in, rec, &shading, wi) {
+            return 0.0;
+        }
+        self.base.pdf(r_in, &shading, wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, &self.shading_record(r_in, rec))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
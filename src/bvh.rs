@@ -0,0 +1,69 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::rtweekend::*;
+use std::sync::Arc;
+
+// Bounding volume hierarchy, so a ray is only tested against the objects whose boxes it passes
+// through. Each node sorts its objects along the longest axis of their bounds and splits them
+// in half; the leaves are the objects themselves.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(mut objects: Vec<Arc<dyn Hittable>>) -> Self {
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, object| Aabb::surrounding(&bbox, &object.bounding_box()));
+
+        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match objects.len() {
+            0 | 1 => {
+                let empty: Arc<dyn Hittable> = Arc::new(HittableList::new(Vec::new()));
+                (objects.pop().unwrap_or_else(|| empty.clone()), empty)
+            }
+            2 => (objects[0].clone(), objects[1].clone()),
+            n => {
+                let axis = bbox.longest_axis();
+                objects.sort_by(|a, b| {
+                    let (a, b) = (a.bounding_box().axis_interval(axis), b.bounding_box().axis_interval(axis));
+                    a.min.total_cmp(&b.min)
+                });
+                let upper = objects.split_off(n / 2);
+                (Arc::new(BvhNode::new(objects)), Arc::new(BvhNode::new(upper)))
+            }
+        };
+
+        Self { left, right, bbox }
+    }
+
+    pub fn from_list(list: &HittableList) -> Self {
+        Self::new(list.objects().to_vec())
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        // Only area lights set this, so clear whatever an earlier object left behind
+        rec.light = None;
+        let hit_left = self.left.hit(r, ray_t, rec, sampler);
+
+        // Some objects change the record even when they miss, so the right side gets its own
+        let mut right_rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        let right_t = Interval::new(ray_t.min, if hit_left { rec.t } else { ray_t.max });
+        if self.right.hit(r, right_t, &mut right_rec, sampler) {
+            *rec = right_rec;
+            return true;
+        }
+        hit_left
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -1,19 +1,55 @@
-use crate::rtweekend::{Color, Vec3, Point3, Ray, degrees_to_radians, write_color, INFINITY, random_in_unit_disk};
+use crate::rtweekend::{Color, Vec3, Point3, Ray, Sampler, degrees_to_radians, INFINITY};
+use crate::hdr_image::HdrImage;
 use crate::hittable::{Hittable, HitRecord};
 use crate::interval::Interval;
-use rand::Rng;
+use crate::light::{Light, power_heuristic};
+use crate::light_bvh::LightBvh;
+use crate::material::Material;
+use crate::sky::SunSky;
+use crate::spectrum::{PathSpectrum, SampledSpectrum, SampledWavelengths};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// One surface interaction along a traced path, as recorded by Camera::trace_path.
+#[derive(Clone, Copy, Debug)]
+pub struct PathVertex<S = Color> {
+    pub depth: i32,
+    pub p: Point3,
+    pub normal: Vec3,
+    pub t: f64,
+    pub front_face: bool,
+    pub throughput: S,
+}
 
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
     pub samples_per_pixel: i32,
     pub max_depth: i32,
+    pub rr_min_depth: i32,
     pub vfov: f64,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    // Flat color seen by rays that escape the scene. With neither this nor an environment they
+    // escape into a default daylight sky.
+    pub background: Option<Color>,
+    // Replaces the background and takes part in light sampling
+    pub environment: Option<Arc<dyn Light>>,
+    pub lights: Vec<Arc<dyn Light>>,
+    // Trace sampled wavelengths instead of RGB, which enables dispersion
+    pub spectral: bool,
+    // Worker threads, 0 for one per core. Each takes square tiles of tile_size pixels in turn.
+    pub threads: usize,
+    pub tile_size: i32,
+    // Renders only the pixels from (x, y) spanning (width, height) of the full image
+    pub crop: Option<(i32, i32, i32, i32)>,
+    // Fixes the random numbers of every pixel sample, so a render can be repeated exactly,
+    // whatever the thread count and tile size. Without it every render is seeded differently.
+    pub seed: Option<u64>,
     pixel_samples_scale: f64,
     image_height: i32,
     center: Point3,
@@ -25,6 +61,9 @@ pub struct Camera {
     w: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    // What rays escaping the scene see: the environment or the default sky, if any
+    sky: Option<Arc<dyn Light>>,
+    light_sampler: LightBvh,
 }
 
 impl Camera {
@@ -34,12 +73,21 @@ impl Camera {
             image_width: 100,
             samples_per_pixel: 10,
             max_depth: 10,
+            rr_min_depth: 3,
             vfov: 90.0,
             lookfrom: Point3::new(0.0, 0.0, 0.0),
             lookat: Point3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            background: None,
+            environment: None,
+            lights: Vec::new(),
+            spectral: false,
+            threads: 0,
+            tile_size: 32,
+            crop: None,
+            seed: None,
             pixel_samples_scale: 1.0,
             image_height: 0,
             center: Point3::new(0.0, 0.0, 0.0),
@@ -51,9 +99,18 @@ impl Camera {
             w: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
+            sky: None,
+            light_sampler: LightBvh::new(&[]),
         }
     }
 
+    // Sets an exact image size. The height is normally derived from the aspect ratio, rounding
+    // down, so the ratio is aimed half a pixel past the height to land on it.
+    pub fn set_image_size(&mut self, width: i32, height: i32) {
+        self.image_width = width;
+        self.aspect_ratio = width as f64 / (height as f64 + 0.5);
+    }
+
     fn initialize(&mut self) {
         // Calculate the image height, and ensure that it's at least 1
         self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
@@ -96,47 +153,192 @@ impl Camera {
         let defocus_radius = self.focus_dist * (degrees_to_radians(self.defocus_angle / 2.0)).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        self.sky = match (&self.environment, self.background) {
+            (Some(env), _) => Some(env.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(Arc::new(SunSky::from_elevation_azimuth(45.0, 0.0, 3.0))),
+        };
+        let mut all_lights = self.lights.clone();
+        all_lights.extend(self.sky.clone());
+        self.light_sampler = LightBvh::new(&all_lights);
     }
 
-    fn ray_color(&self, r: &Ray, depth: i32, world: &dyn Hittable) -> Color {
-        if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+    fn ray_color(&self, r: &Ray, world: &dyn Hittable, sampler: &mut Sampler) -> Color {
+        self.trace_path(r, world, sampler, None)
+    }
+
+    // Follows a single path iteratively, carrying the product of attenuations so far as the
+    // throughput, drawing its random numbers from `sampler`. When `path` is given, one
+    // PathVertex is pushed per surface interaction.
+    pub fn trace_path(&self, r: &Ray, world: &dyn Hittable, sampler: &mut Sampler, path: Option<&mut Vec<PathVertex>>) -> Color {
+        self.integrate(r, world, &|c: Color| c, sampler, path).0
+    }
+
+    // One spectral sample: the camera ray is tagged with the hero wavelength, colors are
+    // upsampled to spectra on the fly and the result is projected back to RGB.
+    fn spectral_sample(&self, r: &Ray, world: &dyn Hittable, sampler: &mut Sampler) -> Color {
+        let mut lambda = SampledWavelengths::sample_visible(sampler.next_f64());
+        let r = r.with_wavelength(lambda.hero());
+        let (l, dispersed) = self.integrate(&r, world, &|c: Color| SampledSpectrum::from_rgb(c, &lambda), sampler, None);
+        if dispersed {
+            lambda.terminate_secondary();
         }
+        lambda.to_rgb(&l)
+    }
+
+    // The path tracing loop shared by RGB and spectral rendering. `lift` turns the RGB values
+    // returned by materials and lights into the carried quantity. Also reports whether the path
+    // went through a dispersive material.
+    //
+    // Each diffuse-like hit samples one light directly (next event estimation); the result is
+    // combined with the BSDF-sampled direction through multiple importance sampling.
+    fn integrate<S: PathSpectrum>(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lift: &dyn Fn(Color) -> S,
+        sampler: &mut Sampler,
+        mut path: Option<&mut Vec<PathVertex<S>>>,
+    ) -> (S, bool) {
+        let mut ray = *r;
+        let mut throughput = S::splat(1.0);
+        let mut radiance = S::splat(0.0);
+        let mut dispersed = false;
+        // Solid angle pdf of the BSDF sample that produced `ray`, zero after a specular bounce
+        let mut bsdf_pdf = 0.0;
+        let mut prev_rec: Option<HitRecord> = None;
+
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+            if !world.hit(&ray, Interval::new(0.001, INFINITY), &mut rec, sampler) {
+                match (&self.sky, self.background) {
+                    (Some(sky), _) => {
+                        let weight = self.emission_weight(sky, prev_rec.as_ref(), bsdf_pdf, &ray);
+                        radiance += throughput * lift(sky.le(&ray)) * weight;
+                    }
+                    (None, Some(background)) => radiance += throughput * lift(background),
+                    (None, None) => {}
+                }
+                break;
+            }
+
+            if let Some(light) = &rec.light {
+                let weight = self.emission_weight(light, prev_rec.as_ref(), bsdf_pdf, &ray);
+                radiance += throughput * lift(light.l(&rec, &-ray.direction())) * weight;
+            }
+
+            if let Some(path) = path.as_deref_mut() {
+                path.push(PathVertex {
+                    depth,
+                    p: rec.p,
+                    normal: rec.normal,
+                    t: rec.t,
+                    front_face: rec.front_face,
+                    throughput,
+                });
+            }
+
+            let mat = match &rec.mat {
+                Some(mat) => mat.clone(),
+                None => break,
+            };
 
-        let mut rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
-        if world.hit(r, Interval::new(0.001, INFINITY), &mut rec) {
-            let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
-            let mut attenuation = Color::new(0.0, 0.0, 0.0);
-            if let Some(mat) = &rec.mat {
-                if mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-                    return attenuation * self.ray_color(&scattered, depth - 1, world);
+            radiance += throughput * lift(mat.emitted(&ray, &rec));
+            radiance += throughput * self.sample_one_light(&ray, &rec, mat.as_ref(), world, lift, sampler);
+
+            let srec = match mat.sample(&ray, &rec, sampler) {
+                Some(srec) => srec,
+                None => break,
+            };
+            let scattered = srec.scattered.with_wavelength(ray.wavelength());
+            bsdf_pdf = srec.pdf;
+            throughput = throughput * lift(srec.attenuation);
+            dispersed |= mat.is_dispersive();
+
+            // Russian roulette: past rr_min_depth bounces, kill dim paths with probability
+            // based on their throughput and boost the survivors to stay unbiased.
+            // max_depth remains a hard cap.
+            if depth >= self.rr_min_depth {
+                let q = throughput.max_component().min(0.95);
+                if sampler.next_f64() >= q {
+                    break;
                 }
+                throughput /= q;
             }
-            return Color::new(0.0, 0.0, 0.0);
+
+            ray = scattered;
+            prev_rec = Some(rec);
         }
 
-        let unit_direction = r.direction().unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        // blue to white gradient
-        // uses the (1-a) * white + a * blue
-        Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+        (radiance, dispersed)
     }
 
-    fn sample_square(&self) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let x = -0.5 + rng.gen_range(0.0..1.0);
-        let y = -0.5 + rng.gen_range(0.0..1.0);
+    // MIS weight for emission found by following the BSDF-sampled `ray` from `prev`.
+    fn emission_weight(&self, light: &Arc<dyn Light>, prev: Option<&HitRecord>, bsdf_pdf: f64, ray: &Ray) -> f64 {
+        match prev {
+            Some(prev) if bsdf_pdf > 0.0 => {
+                let light_pdf = self.light_sampler.pmf(prev, light) * light.pdf_li(prev, &ray.direction());
+                power_heuristic(bsdf_pdf, light_pdf)
+            }
+            _ => 1.0,
+        }
+    }
+
+    // Picks one light with the light BVH and returns its MIS-weighted direct contribution at `rec`.
+    fn sample_one_light<S: PathSpectrum>(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        mat: &dyn Material,
+        world: &dyn Hittable,
+        lift: &dyn Fn(Color) -> S,
+        sampler: &mut Sampler,
+    ) -> S {
+        let black = S::splat(0.0);
+        let (light, select_pdf) = match self.light_sampler.sample(rec, sampler.next_f64()) {
+            Some(picked) => picked,
+            None => return black,
+        };
+
+        let ls = match light.sample_li(rec, sampler) {
+            Some(ls) if ls.pdf > 0.0 => ls,
+            _ => return black,
+        };
+        let f = mat.eval(r_in, rec, &ls.wi);
+        if f.max_component() <= 0.0 || ls.li.max_component() <= 0.0 {
+            return black;
+        }
+
+        let shadow_ray = Ray::new(rec.p, ls.wi);
+        let max_t = if ls.distance < INFINITY { ls.distance * (1.0 - 1e-4) } else { INFINITY };
+        let mut shadow_rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        if world.hit(&shadow_ray, Interval::new(0.001, max_t), &mut shadow_rec, sampler) {
+            return black;
+        }
+
+        let weight = if ls.is_delta {
+            1.0
+        } else {
+            power_heuristic(ls.pdf * select_pdf, mat.pdf(r_in, rec, &ls.wi))
+        };
+        lift(f) * lift(ls.li) * (weight / (ls.pdf * select_pdf))
+    }
+
+    fn sample_square(&self, sampler: &mut Sampler) -> Vec3 {
+        let x = -0.5 + sampler.next_f64();
+        let y = -0.5 + sampler.next_f64();
         // eprintln!("x: {}, y: {}", x, y);
         Vec3::new(x, y, 0.0)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = sampler.in_unit_disk();
         self.center + self.defocus_disk_u * p.x() + self.defocus_disk_v * p.y()
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = self.sample_square();
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut Sampler) -> Ray {
+        let offset = self.sample_square(sampler);
         let pixel_sample = self.pixel00_loc + 
             ((i as f64 + offset.x()) * self.pixel_delta_u) + 
             ((j as f64 + offset.y()) * self.pixel_delta_v);
@@ -144,33 +346,103 @@ impl Camera {
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = pixel_sample - ray_origin;
         Ray::new(ray_origin, ray_direction)
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
+    // Renders the image, or its crop region, in linear color.
+    pub fn render_image(&mut self, world: &dyn Hittable) -> HdrImage {
         self.initialize();
+        let (x0, y0, width, height) = self.region();
+        let tile_size = self.tile_size.max(1);
+        let tiles_x = (width + tile_size - 1) / tile_size;
+        let tiles_y = (height + tile_size - 1) / tile_size;
+        let tile_count = (tiles_x * tiles_y) as usize;
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
 
-        // Render
-        println!("P3\n{} {}\n255", self.image_width, self.image_height);
-
-        for j in 0..self.image_height {
-            eprintln!("\rScanlines remaining: {}", self.image_height - j);
-            // eprintln!("samples_per_pixel: {}", self.samples_per_pixel);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&r, self.max_depth, world);
-                }
+        let seed = self.seed.unwrap_or_else(|| Sampler::from_entropy().next_u64());
+        let next_tile = AtomicUsize::new(0);
+        let tiles_done = AtomicUsize::new(0);
+        let pixels = Mutex::new(vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize]);
+        let camera = &*self;
+        thread::scope(|scope| {
+            for _ in 0..threads.min(tile_count) {
+                scope.spawn(|| loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tile_count {
+                        break;
+                    }
+
+                    // Tile position relative to the region
+                    let tx = (index as i32 % tiles_x) * tile_size;
+                    let ty = (index as i32 / tiles_x) * tile_size;
+                    let tile = camera.render_tile(world, seed, x0 + tx, y0 + ty, tile_size.min(width - tx), tile_size.min(height - ty));
+
+                    let mut pixels = pixels.lock().unwrap();
+                    for (j, row) in tile.iter().enumerate() {
+                        let start = ((ty + j as i32) * width + tx) as usize;
+                        pixels[start..start + row.len()].copy_from_slice(row);
+                    }
+                    let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                    eprint!("\rTiles remaining: {:<8}", tile_count - done);
+                });
+            }
+        });
+        eprintln!("\nDone.");
+
+        HdrImage::new(width as usize, height as usize, pixels.into_inner().unwrap())
+    }
 
-                let result = self.pixel_samples_scale * pixel_color;
-                // eprintln!("result: x: {}, y: {}, z: {}", result.x(), result.y(), result.z());
-                write_color(&mut std::io::stdout(), result);
+    // Left, top, width and height of the part of the image to render
+    fn region(&self) -> (i32, i32, i32, i32) {
+        // A crop always keeps at least one pixel, even of an empty image
+        let (w, h) = (self.image_width.max(1), self.image_height.max(1));
+        match self.crop {
+            Some((x, y, cw, ch)) => {
+                let (x, y) = (x.clamp(0, w - 1), y.clamp(0, h - 1));
+                (x, y, cw.clamp(1, w - x), ch.clamp(1, h - y))
             }
+            None => (0, 0, w, h),
         }
-        eprintln!("Done.");
     }
-}
\ No newline at end of file
+
+    // Rows of averaged samples for the pixels from (x0, y0) spanning (width, height)
+    fn render_tile(&self, world: &dyn Hittable, seed: u64, x0: i32, y0: i32, width: i32, height: i32) -> Vec<Vec<Color>> {
+        (y0..y0 + height)
+            .map(|j| {
+                (x0..x0 + width)
+                    .map(|i| {
+                        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                        for sample in 0..self.samples_per_pixel {
+                            // Every sample has its own random stream: the camera draws from it
+                            // first, then the rest of the path
+                            let mut sampler = Sampler::for_pixel_sample(seed, i, j, sample);
+                            let r = self.get_ray(i, j, &mut sampler);
+                            pixel_color += if self.spectral {
+                                self.spectral_sample(&r, world, &mut sampler)
+                            } else {
+                                self.ray_color(&r, world, &mut sampler)
+                            };
+                        }
+                        self.pixel_samples_scale * pixel_color
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Traces one sample through pixel (i, j) and returns every bounce it made, for debugging.
+    pub fn debug_path(&mut self, i: i32, j: i32, world: &dyn Hittable) -> (Color, Vec<PathVertex>) {
+        self.initialize();
+        let mut sampler = Sampler::for_pixel_sample(self.seed.unwrap_or(0), i, j, 0);
+        let r = self.get_ray(i, j, &mut sampler);
+        let mut path = Vec::new();
+        let color = self.trace_path(&r, world, &mut sampler, Some(&mut path));
+        (color, path)
+    }
+}
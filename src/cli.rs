@@ -0,0 +1,175 @@
+use crate::camera::Camera;
+use crate::image_output::ImageFormat;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: rust-ray-tracer [OPTIONS] [SCENE]
+
+Renders SCENE, a scene description file, or one of the built-in presets when none is given.
+
+Options:
+  -p, --preset NAME        Built-in scene to render (default: weekend-final)
+      --list-presets       List the built-in scenes
+  -o, --output PATH        Write the image to PATH instead of stdout
+  -f, --format FORMAT      Image format: ppm, pfm or png (default: from the output
+                           extension, otherwise ppm)
+  -W, --width N            Image width in pixels
+  -H, --height N           Image height in pixels; with only one of the two, the
+                           scene's aspect ratio is kept
+  -s, --spp N              Samples per pixel
+  -d, --depth N            Maximum path length in bounces
+      --seed N             Seed for the random numbers, making renders repeatable
+  -j, --threads N          Worker threads (default: one per core)
+      --tile-size N        Side of the square tiles handed to threads (default: 32)
+      --crop X,Y,W,H       Render only the W by H pixels starting at column X, row Y
+      --debug-pixel X,Y    Print the bounces of one path through column X, row Y
+                           instead of rendering
+  -h, --help               Print this help
+";
+
+// Options given on the command line. Anything left unset keeps the scene's own value.
+#[derive(Default, Debug)]
+pub struct Options {
+    pub help: bool,
+    pub list_presets: bool,
+    pub scene: Option<PathBuf>,
+    pub preset: Option<String>,
+    pub output: Option<PathBuf>,
+    pub format: Option<ImageFormat>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub tile_size: Option<i32>,
+    pub crop: Option<(i32, i32, i32, i32)>,
+    pub debug_pixel: Option<(i32, i32)>,
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, option))
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<i32, String> {
+    match parse_number::<i32>(option, value)? {
+        n if n > 0 => Ok(n),
+        _ => Err(format!("{} must be at least 1", option)),
+    }
+}
+
+fn parse_crop(value: &str) -> Result<(i32, i32, i32, i32), String> {
+    let parts = value.split(',').map(|p| parse_number::<i32>("--crop", p.trim())).collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y, w, h] if x >= 0 && y >= 0 && w > 0 && h > 0 => Ok((x, y, w, h)),
+        _ => Err(format!("--crop takes X,Y,W,H with a positive size, got '{}'", value)),
+    }
+}
+
+fn parse_pixel(value: &str) -> Result<(i32, i32), String> {
+    let parts = value.split(',').map(|p| parse_number::<i32>("--debug-pixel", p.trim())).collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y] if x >= 0 && y >= 0 => Ok((x, y)),
+        _ => Err(format!("--debug-pixel takes X,Y, got '{}'", value)),
+    }
+}
+
+impl Options {
+    // Parses the arguments that follow the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                if options.scene.is_some() {
+                    return Err(format!("unexpected argument '{}'; only one scene can be rendered", arg));
+                }
+                options.scene = Some(PathBuf::from(arg));
+                continue;
+            }
+
+            // Both "--option value" and "--option=value" are accepted
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None),
+            };
+            match name.as_str() {
+                "-h" | "--help" => {
+                    options.help = true;
+                    continue;
+                }
+                "--list-presets" => {
+                    options.list_presets = true;
+                    continue;
+                }
+                "-p" | "--preset" | "-o" | "--output" | "-f" | "--format" | "-W" | "--width" | "-H" | "--height"
+                | "-s" | "--spp" | "-d" | "--depth" | "--seed" | "-j" | "--threads" | "--tile-size" | "--crop"
+                | "--debug-pixel" => {}
+                _ => return Err(format!("unknown option '{}'", name)),
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("{} needs a value", name)),
+            };
+
+            match name.as_str() {
+                "-p" | "--preset" => options.preset = Some(value),
+                "-o" | "--output" => options.output = Some(PathBuf::from(value)),
+                "-f" | "--format" => {
+                    options.format = Some(ImageFormat::from_name(&value).ok_or(format!("unknown image format '{}'", value))?)
+                }
+                "-W" | "--width" => options.width = Some(parse_positive(&name, &value)?),
+                "-H" | "--height" => options.height = Some(parse_positive(&name, &value)?),
+                "-s" | "--spp" => options.samples_per_pixel = Some(parse_positive(&name, &value)?),
+                "-d" | "--depth" => options.max_depth = Some(parse_positive(&name, &value)?),
+                "--seed" => options.seed = Some(parse_number(&name, &value)?),
+                "-j" | "--threads" => options.threads = Some(parse_positive(&name, &value)? as usize),
+                "--tile-size" => options.tile_size = Some(parse_positive(&name, &value)?),
+                "--crop" => options.crop = Some(parse_crop(&value)?),
+                "--debug-pixel" => options.debug_pixel = Some(parse_pixel(&value)?),
+                _ => unreachable!(),
+            }
+        }
+        if options.scene.is_some() && options.preset.is_some() {
+            return Err("a scene file and a preset cannot both be rendered".to_string());
+        }
+        Ok(options)
+    }
+
+    // Format to write the image in
+    pub fn output_format(&self) -> ImageFormat {
+        self.format
+            .or_else(|| self.output.as_ref().and_then(ImageFormat::from_path))
+            .unwrap_or(ImageFormat::Ppm)
+    }
+
+    // Overrides the camera settings that were given on the command line
+    pub fn apply(&self, camera: &mut Camera) {
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => camera.set_image_size(w, h),
+            (Some(w), None) => camera.image_width = w,
+            (None, Some(h)) => {
+                // Keeps the aspect ratio, but goes through set_image_size so the height is exact
+                let w = ((h as f64 * camera.aspect_ratio).round() as i32).max(1);
+                camera.set_image_size(w, h);
+            }
+            (None, None) => {}
+        }
+        if let Some(spp) = self.samples_per_pixel {
+            camera.samples_per_pixel = spp;
+        }
+        if let Some(depth) = self.max_depth {
+            camera.max_depth = depth;
+        }
+        if let Some(threads) = self.threads {
+            camera.threads = threads;
+        }
+        if let Some(tile_size) = self.tile_size {
+            camera.tile_size = tile_size;
+        }
+        camera.seed = self.seed.or(camera.seed);
+        camera.crop = self.crop.or(camera.crop);
+    }
+}
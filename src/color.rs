@@ -11,16 +11,39 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     }
 }
 
-pub fn write_color(out: &mut dyn std::io::Write, pixel_color: Color) {
-    let mut r = linear_to_gamma(pixel_color.x());
-    let mut g = linear_to_gamma(pixel_color.y());
-    let mut b = linear_to_gamma(pixel_color.z());
+// Decodes an sRGB-encoded component in [0,1] to linear, as stored in 8-bit textures
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// CIE XYZ to linear sRGB (D65)
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// Gamma-encoded 8-bit components of a linear color, for display
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
+    let r = linear_to_gamma(pixel_color.x());
+    let g = linear_to_gamma(pixel_color.y());
+    let b = linear_to_gamma(pixel_color.z());
 
     // Translate the [0,1] component values to the byte range [0,255]
     let intensity = Interval::new(0.0, 0.999);
-    let ir = (255.999 * intensity.clamp(r)) as i32;
-    let ig = (255.999 * intensity.clamp(g)) as i32;
-    let ib = (255.999 * intensity.clamp(b)) as i32;
-
-    out.write_fmt(format_args!("{} {} {}\n", ir, ig, ib));
+    let ir = (255.999 * intensity.clamp(r)) as u8;
+    let ig = (255.999 * intensity.clamp(g)) as u8;
+    let ib = (255.999 * intensity.clamp(b)) as u8;
+    [ir, ig, ib]
 }
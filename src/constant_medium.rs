@@ -0,0 +1,96 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::{Isotropic, Material};
+use crate::rtweekend::*;
+use std::sync::Arc;
+
+// Homogeneous participating medium filling a closed `boundary`, such as smoke, fog or the
+// inside of a subsurface scattering object.
+//
+// Free-flight distances are sampled against the largest extinction coefficient. When the
+// channels differ, some of those collisions are null collisions that the phase function lets
+// through unchanged, which keeps the estimate unbiased for every channel.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self::chromatic(boundary, Color::new(density, density, density), albedo)
+    }
+
+    // Medium with a separate extinction coefficient (collisions per unit distance) per channel
+    pub fn chromatic(boundary: Arc<dyn Hittable>, density: Color, albedo: Color) -> Self {
+        let majorant = density.max_component().max(1e-12);
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / majorant,
+            phase_function: Arc::new(Isotropic::new(albedo).with_extinction_ratio(density / majorant)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    // Walks the boundary crossings along the ray, entering at front faces and leaving at back
+    // faces, so the medium can fill concave shapes too. One exponential free-flight distance
+    // is spent across all the stretches inside.
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        let mut crossing = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        let ray_length = r.direction().length();
+        let mut flight_distance = None;
+        let mut entered = None;
+        // Surfaces entered and not yet left, in case parts of the boundary overlap
+        let mut depth = 0;
+        let mut t = -INFINITY;
+
+        while t < ray_t.max && self.boundary.hit(r, Interval::new(t, INFINITY), &mut crossing, sampler) {
+            t = crossing.t + 0.0001;
+            if crossing.front_face {
+                if depth == 0 {
+                    entered = Some(crossing.t);
+                }
+                depth += 1;
+                continue;
+            }
+            if depth > 1 {
+                depth -= 1;
+                continue;
+            }
+            depth = 0;
+            // A first crossing from the inside means the ray starts in the medium
+            let start = entered.take().unwrap_or(-INFINITY).max(ray_t.min).max(0.0);
+            let end = crossing.t.min(ray_t.max);
+            if start >= end {
+                continue;
+            }
+
+            let distance_inside = (end - start) * ray_length;
+            let remaining = flight_distance.get_or_insert_with(|| self.neg_inv_density * sampler.next_f64().ln());
+            if *remaining > distance_inside {
+                *remaining -= distance_inside;
+                continue;
+            }
+
+            rec.t = start + *remaining / ray_length;
+            rec.p = r.at(rec.t);
+            // A medium has no surface normal; a zero normal tells light sampling to skip the cosine
+            rec.normal = Vec3::new(0.0, 0.0, 0.0);
+            rec.geometric_normal = rec.normal;
+            rec.front_face = true;
+            rec.u = 0.0;
+            rec.v = 0.0;
+            rec.dpdu = Vec3::new(0.0, 0.0, 0.0);
+            rec.dpdv = Vec3::new(0.0, 0.0, 0.0);
+            rec.mat = Some(self.phase_function.clone());
+            return true;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
@@ -0,0 +1,89 @@
+// Piecewise-constant 1D distribution over [0,1), built from a tabulated function.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f64]) -> Self {
+        let n = func.len();
+        let func: Vec<f64> = func.iter().map(|f| f.abs()).collect();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1] / n as f64;
+        }
+
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            // Fall back to a uniform distribution when the function is zero everywhere
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n as f64;
+            }
+        } else {
+            for c in cdf.iter_mut() {
+                *c /= func_int;
+            }
+        }
+
+        Self { func, cdf, func_int }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn func_int(&self) -> f64 {
+        self.func_int
+    }
+
+    // Maps a uniform u in [0,1) to (x, pdf(x), bucket index).
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // Largest index whose cdf value is <= u
+        let offset = self.cdf.partition_point(|&c| c <= u).saturating_sub(1).min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let pdf = if self.func_int > 0.0 { self.func[offset] / self.func_int } else { 1.0 };
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+}
+
+// Piecewise-constant 2D distribution over [0,1)^2: a marginal over rows (v) and a conditional
+// distribution over columns (u) for every row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // `func` holds nu * nv values, row by row.
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<Distribution1D> = (0..nv).map(|v| Distribution1D::new(&func[v * nu..(v + 1) * nu])).collect();
+        let marginal_func: Vec<f64> = conditional.iter().map(|d| d.func_int()).collect();
+        let marginal = Distribution1D::new(&marginal_func);
+        Self { conditional, marginal }
+    }
+
+    // Maps two uniform numbers to a point (u, v) and its density with respect to area in [0,1)^2.
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> (f64, f64, f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let nu = self.conditional[0].count();
+        let nv = self.marginal.count();
+        let iu = ((u * nu as f64) as usize).min(nu - 1);
+        let iv = ((v * nv as f64) as usize).min(nv - 1);
+        if self.marginal.func_int() == 0.0 {
+            return 1.0;
+        }
+        self.conditional[iv].func[iu] / self.marginal.func_int()
+    }
+}
@@ -0,0 +1,105 @@
+use crate::distribution::Distribution2D;
+use crate::hdr_image::HdrImage;
+use crate::hittable::HitRecord;
+use crate::light::{Light, LightSample};
+use crate::rtweekend::*;
+use std::io::Result;
+use std::path::Path;
+
+// Equirectangular (latitude-longitude) HDR environment, importance sampled by pixel luminance.
+pub struct EnvironmentLight {
+    // Rotation about the world up axis, in degrees
+    pub rotation: f64,
+    pub intensity: f64,
+    image: HdrImage,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight {
+    pub fn new(image: HdrImage, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width(), image.height());
+
+        // Weight each pixel by sin(theta) to undo the stretching towards the poles
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                func.push(luminance(image.pixel(x, y)) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, width, height);
+
+        Self { rotation, intensity, image, distribution }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, rotation: f64, intensity: f64) -> Result<Self> {
+        Ok(Self::new(HdrImage::load(path)?, rotation, intensity))
+    }
+
+    fn rotate_y(v: Vec3, degrees: f64) -> Vec3 {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        Vec3::new(cos * v.x() + sin * v.z(), v.y(), -sin * v.x() + cos * v.z())
+    }
+
+    // Map-space unit direction to (u, v) image coordinates, v = 0 at the top (+y).
+    fn direction_to_uv(d: Vec3) -> (f64, f64) {
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = (-d.z()).atan2(d.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(u: f64, v: f64) -> Vec3 {
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+        Vec3::new(-sin_theta * cos_phi, cos_theta, sin_theta * sin_phi)
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = (u * self.image.width() as f64) as usize;
+        let y = (v * self.image.height() as f64) as usize;
+        self.intensity * self.image.pixel(x, y)
+    }
+
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let d = Self::rotate_y(direction.unit_vector(), -self.rotation);
+        let (u, v) = Self::direction_to_uv(d);
+        self.lookup(u, v)
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _rec: &HitRecord, sampler: &mut Sampler) -> Option<LightSample> {
+        let (u, v, map_pdf) = self.distribution.sample_continuous(sampler.next_f64(), sampler.next_f64());
+        if map_pdf == 0.0 {
+            return None;
+        }
+
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+
+        let wi = Self::rotate_y(Self::uv_to_direction(u, v), self.rotation);
+        Some(LightSample {
+            wi,
+            li: self.lookup(u, v),
+            pdf: map_pdf / (2.0 * PI * PI * sin_theta),
+            distance: INFINITY,
+            is_delta: false,
+        })
+    }
+
+    fn pdf_li(&self, _rec: &HitRecord, wi: &Vec3) -> f64 {
+        let d = Self::rotate_y(wi.unit_vector(), -self.rotation);
+        let (u, v) = Self::direction_to_uv(d);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        self.radiance(&r.direction())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::aabb::Aabb;
+use crate::hittable::{hit_skipping, HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::rtweekend::*;
+use std::sync::Arc;
+
+// Per-object control over the two sides of a surface. Without it every surface is two-sided
+// and shows its material on both faces.
+//
+// The back face, the side opposite the outward normal, can be culled so rays pass through it
+// from behind, or given a material of its own, like a leaf with a differently colored underside.
+pub struct Faces {
+    object: Arc<dyn Hittable>,
+    cull_back: bool,
+    back_material: Option<Arc<dyn Material>>,
+}
+
+impl Faces {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Self { object, cull_back: false, back_material: None }
+    }
+
+    pub fn with_back_face_culling(mut self) -> Self {
+        self.cull_back = true;
+        self
+    }
+
+    pub fn with_back_material(mut self, mat: Arc<dyn Material>) -> Self {
+        self.back_material = Some(mat);
+        self
+    }
+}
+
+impl Hittable for Faces {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !hit_skipping(self.object.as_ref(), r, ray_t, rec, sampler, |rec, _| self.cull_back && !rec.front_face) {
+            return false;
+        }
+        if !rec.front_face {
+            if let Some(mat) = &self.back_material {
+                rec.mat = Some(mat.clone());
+            }
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
+    }
+
+    fn surface_area(&self) -> f64 {
+        self.object.surface_area()
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        self.object.normal_cone()
+    }
+}
@@ -0,0 +1,164 @@
+use crate::rtweekend::*;
+use std::ops::{Add, Div, Mul, Sub};
+
+// Minimal complex number for conductor Fresnel terms
+#[derive(Copy, Clone, Debug)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn sqrt(&self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+
+    // e^(i z)
+    pub fn exp_i(&self) -> Complex {
+        let scale = (-self.im).exp();
+        Complex::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let scale = 1.0 / o.norm();
+        Complex::new(scale * (self.re * o.re + self.im * o.im), scale * (self.im * o.re - self.re * o.im))
+    }
+}
+
+// Schlick's approximation with normal-incidence reflectance r0
+pub fn schlick(cosine: f64, r0: f64) -> f64 {
+    r0 + (1.0 - r0) * (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+pub fn schlick_color(cosine: f64, r0: Color) -> Color {
+    let w = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+    r0 + w * (Color::new(1.0, 1.0, 1.0) - r0)
+}
+
+// Exact unpolarized Fresnel reflectance at a dielectric boundary. `eta` is the ratio of the
+// transmitted side's IOR over the incident side's; a negative cos_theta_i means the light arrives
+// from the transmitted side and the ratio is inverted.
+pub fn fr_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction eta + i k
+pub fn fr_complex(cos_theta_i: f64, eta: Complex) -> f64 {
+    let cos_theta_i = Complex::new(cos_theta_i.clamp(0.0, 1.0), 0.0);
+    let one = Complex::new(1.0, 0.0);
+    let sin2_theta_i = one - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    let cos_theta_t = (one - sin2_theta_t).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parl.norm() + r_perp.norm()) / 2.0
+}
+
+pub fn fr_complex_color(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        fr_complex(cos_theta_i, Complex::new(eta.x(), k.x())),
+        fr_complex(cos_theta_i, Complex::new(eta.y(), k.y())),
+        fr_complex(cos_theta_i, Complex::new(eta.z(), k.z())),
+    )
+}
+
+// A thin transparent film on top of a surface, such as soap or oil. Light reflected off the top
+// and the bottom of the film interferes, which tints the reflection depending on the viewing
+// angle and the wavelength.
+#[derive(Copy, Clone, Debug)]
+pub struct ThinFilm {
+    // Film thickness in nanometers
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self { thickness: thickness.max(0.0), ior }
+    }
+
+    // Reflectance at wavelength `lambda` (nm) for light arriving from a medium with IOR `eta_i`
+    // onto the film, over a base with complex IOR `eta_t`. Sums all internal reflections
+    // (Airy) for both polarizations and averages them.
+    pub fn reflectance(&self, cos_theta_i: f64, eta_i: f64, eta_t: Complex, lambda: f64) -> f64 {
+        let one = Complex::new(1.0, 0.0);
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        let sin2_theta_i = Complex::new(eta_i * eta_i * (1.0 - cos_theta_i * cos_theta_i), 0.0);
+        let cos_in = |n: Complex| (one - sin2_theta_i / (n * n)).sqrt();
+
+        let (n1, n2, n3) = (Complex::new(eta_i, 0.0), Complex::new(self.ior, 0.0), eta_t);
+        let (c1, c2, c3) = (Complex::new(cos_theta_i, 0.0), cos_in(n2), cos_in(n3));
+
+        // Phase difference of one round trip through the film
+        let phase = Complex::new(4.0 * PI * self.thickness / lambda, 0.0) * n2 * c2;
+        let shift = phase.exp_i();
+
+        let r_perp = |na: Complex, ca: Complex, nb: Complex, cb: Complex| (na * ca - nb * cb) / (na * ca + nb * cb);
+        let r_parl = |na: Complex, ca: Complex, nb: Complex, cb: Complex| (nb * ca - na * cb) / (nb * ca + na * cb);
+        let airy = |r12: Complex, r23: Complex| (r12 + r23 * shift).norm() / (one + r12 * r23 * shift).norm();
+
+        let perp = airy(r_perp(n1, c1, n2, c2), r_perp(n2, c2, n3, c3));
+        let parl = airy(r_parl(n1, c1, n2, c2), r_parl(n2, c2, n3, c3));
+        ((perp + parl) / 2.0).clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,792 @@
+use crate::alpha_mask::AlphaMask;
+use crate::bump::Bumped;
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::json::Json;
+use crate::light::{DiffuseAreaLight, DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::Material;
+use crate::png::PngImage;
+use crate::principled::Principled;
+use crate::rtweekend::*;
+use crate::scene::{Scene, SceneError};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use crate::triangle::{make_mesh, Triangle, TriangleMesh};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Importer for glTF 2.0 scenes, as .gltf (JSON with external or embedded buffers) or .glb
+// (binary container):
+//
+//     the default scene's node hierarchy, with matrix or translation/rotation/scale transforms
+//     triangle mesh primitives (triangles, strips and fans) with positions, normals and the
+//         first set of texture coordinates
+//     metallic-roughness materials with base color, metallic-roughness, normal and emissive
+//         textures, alpha masking and blending, and the KHR_materials_emissive_strength,
+//         KHR_materials_transmission and KHR_materials_ior extensions
+//     perspective cameras
+//     KHR_lights_punctual point, spot and directional lights
+//
+// Each mesh is built once and placed by every node that uses it through an Instance. Primitives
+// with a constant emissive color are made into area lights instead, so they get sampled.
+//
+// Textures must be PNG; other images, texture transforms, normal texture scales, occlusion,
+// skins, morph targets and animations are left out, with a warning for those that change the
+// image. Light intensities are used as they are, without converting from photometric units.
+// glTF's axes are the same as the renderer's, +y up with cameras looking down -z.
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+fn error<T>(message: String) -> Result<T> {
+    Err(SceneError { line: 0, column: 0, message })
+}
+
+// Image settings, since glTF cameras don't give an image size
+const DEFAULT_WIDTH: i32 = 800;
+const DEFAULT_ASPECT: f64 = 16.0 / 9.0;
+
+const SUPPORTED_EXTENSIONS: [&str; 4] =
+    ["KHR_lights_punctual", "KHR_materials_emissive_strength", "KHR_materials_ior", "KHR_materials_transmission"];
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).or_else(|e| error(format!("can't read scene file {}: {}", path.display(), e)))?;
+    let (text, binary) = if bytes.starts_with(b"glTF") { split_glb(&bytes)? } else { (&bytes[..], None) };
+    let text = std::str::from_utf8(text).or_else(|_| error("glTF JSON is not valid UTF-8".to_string()))?;
+    let doc = Json::parse(text).map_err(|e| SceneError { line: e.line, column: e.column, message: e.message })?;
+
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut loader = Loader::new(&doc, base_dir, binary)?;
+    loader.run()?;
+    Ok(loader.finish())
+}
+
+// JSON and BIN chunks of a .glb file
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<Vec<u8>>)> {
+    let word = |pos: usize| bytes.get(pos..pos + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+    if word(4) != Some(2) {
+        return error("only version 2 of the binary glTF container is supported".to_string());
+    }
+
+    let end = word(8).unwrap_or(0).min(bytes.len());
+    let mut pos = 12;
+    let (mut json, mut binary) = (None, None);
+    while pos + 8 <= end {
+        let (length, kind) = (word(pos).unwrap(), word(pos + 4).unwrap());
+        let Some(data) = bytes.get(pos + 8..pos + 8 + length) else { return error("truncated .glb chunk".to_string()) };
+        match kind {
+            0x4e4f534a if json.is_none() => json = Some(data),
+            0x004e4942 if binary.is_none() => binary = Some(data.to_vec()),
+            _ => {}
+        }
+        pos += 8 + length;
+    }
+    match json {
+        Some(json) => Ok((json, binary)),
+        None => error(".glb file has no JSON chunk".to_string()),
+    }
+}
+
+// Json accessors
+
+fn number(obj: &Json, key: &str, default: f64) -> f64 {
+    obj.get(key).and_then(Json::as_f64).unwrap_or(default)
+}
+
+fn index(obj: &Json, key: &str) -> Option<usize> {
+    obj.get(key).and_then(Json::as_usize)
+}
+
+// Array of numbers under `key`, as long as `default`, or `default` when missing
+fn numbers(obj: &Json, key: &str, default: &[f64]) -> Result<Vec<f64>> {
+    match obj.get(key) {
+        None => Ok(default.to_vec()),
+        Some(value) => match value.as_f64_vec() {
+            Some(values) if values.len() == default.len() => Ok(values),
+            _ => error(format!("\"{}\" must be an array of {} numbers", key, default.len())),
+        },
+    }
+}
+
+fn color(values: &[f64]) -> Color {
+    Color::new(values[0], values[1], values[2])
+}
+
+// Item `i` of the top-level array `array`
+fn item<'a>(doc: &'a Json, array: &str, i: usize) -> Result<&'a Json> {
+    match doc.get(array).and_then(Json::as_array).and_then(|items| items.get(i)) {
+        Some(item) => Ok(item),
+        None => error(format!("reference to {} {}, which doesn't exist", array, i)),
+    }
+}
+
+fn extension<'a>(obj: &'a Json, name: &str) -> Option<&'a Json> {
+    obj.get("extensions")?.get(name)
+}
+
+// Buffer data
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b'\n' | b'\r' | b' ' => continue,
+            _ => return None,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Contents of a buffer or image uri: embedded base64 data or a file next to the scene
+fn read_uri(base_dir: &Path, uri: &str) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let Some((_, payload)) = data.split_once(";base64,") else {
+            return error("data uris must be base64 encoded".to_string());
+        };
+        return base64_decode(payload).map_or_else(|| error("bad base64 data in uri".to_string()), Ok);
+    }
+    let path = base_dir.join(percent_decode(uri));
+    fs::read(&path).or_else(|e| error(format!("can't read {}: {}", path.display(), e)))
+}
+
+// A texture channel, or all three, scaled by a constant factor
+struct Factored {
+    tex: Arc<dyn Texture>,
+    channel: Option<usize>,
+    factor: Color,
+}
+
+impl Texture for Factored {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let c = self.tex.value(u, v, p);
+        match self.channel {
+            Some(i) => c[i] * self.factor,
+            None => c * self.factor,
+        }
+    }
+}
+
+// Opacity of 1 where the first channel reaches `cutoff` and 0 elsewhere, for alpha masks
+struct Threshold {
+    tex: Arc<dyn Texture>,
+    cutoff: f64,
+}
+
+impl Texture for Threshold {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let opaque = if self.tex.value(u, v, p).x() >= self.cutoff { 1.0 } else { 0.0 };
+        Color::new(opaque, opaque, opaque)
+    }
+}
+
+// How an image's pixels are turned into texture values
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Decode {
+    Srgb,
+    Linear,
+    Alpha,
+}
+
+// A glTF material as the pieces the renderer builds it from
+struct SurfaceMaterial {
+    material: Arc<dyn Material>,
+    // Opacity, for alpha masked or blended materials
+    alpha: Option<Arc<dyn Texture>>,
+    // Constant emission and two-sidedness, for primitives that become area lights
+    light: Option<(Color, bool)>,
+}
+
+struct Primitive {
+    mesh: Arc<TriangleMesh>,
+    surface: Arc<SurfaceMaterial>,
+    // The mesh under a BVH, in object space
+    shape: Arc<dyn Hittable>,
+}
+
+struct Loader<'a> {
+    doc: &'a Json,
+    base_dir: PathBuf,
+    buffers: Vec<Vec<u8>>,
+    images: HashMap<usize, Option<Arc<PngImage>>>,
+    textures: HashMap<(usize, Decode), Option<Arc<dyn Texture>>>,
+    materials: HashMap<Option<usize>, Arc<SurfaceMaterial>>,
+    meshes: HashMap<usize, Arc<Vec<Primitive>>>,
+    camera: Camera,
+    has_camera: bool,
+    world: Vec<Arc<dyn Hittable>>,
+    warnings: HashSet<String>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(doc: &'a Json, base_dir: PathBuf, binary: Option<Vec<u8>>) -> Result<Self> {
+        let asset_version = doc.get("asset").and_then(|a| a.get("version")).and_then(Json::as_str);
+        if !asset_version.is_some_and(|v| v.starts_with("2.")) {
+            return error("not a glTF 2.0 file".to_string());
+        }
+
+        // Only the first buffer may live in the .glb's binary chunk
+        let mut binary = binary;
+        let mut buffers = Vec::new();
+        for (i, buffer) in doc.get("buffers").and_then(Json::as_array).unwrap_or_default().iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => read_uri(&base_dir, uri)?,
+                None if i == 0 && binary.is_some() => binary.take().unwrap(),
+                None => return error(format!("buffer {} has no data", i)),
+            };
+            if data.len() < index(buffer, "byteLength").unwrap_or(0) {
+                return error(format!("buffer {} is shorter than its byteLength", i));
+            }
+            buffers.push(data);
+        }
+
+        let mut camera = Camera::new();
+        camera.samples_per_pixel = 64;
+        camera.max_depth = 10;
+        camera.defocus_angle = 0.0;
+        camera.set_image_size(DEFAULT_WIDTH, (DEFAULT_WIDTH as f64 / DEFAULT_ASPECT).round() as i32);
+
+        Ok(Self {
+            doc,
+            base_dir,
+            buffers,
+            images: HashMap::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            camera,
+            has_camera: false,
+            world: Vec::new(),
+            warnings: HashSet::new(),
+        })
+    }
+
+    fn warn(&mut self, message: &str) {
+        if self.warnings.insert(message.to_string()) {
+            eprintln!("warning: {}", message);
+        }
+    }
+
+    fn run(&mut self) -> Result<()> {
+        let doc = self.doc;
+        for ext in doc.get("extensionsRequired").and_then(Json::as_array).unwrap_or_default() {
+            let ext = ext.as_str().unwrap_or_default();
+            if !SUPPORTED_EXTENSIONS.contains(&ext) {
+                self.warn(&format!("required extension {} is not supported", ext));
+            }
+        }
+        for name in ["skins", "animations"] {
+            if doc.get(name).and_then(Json::as_array).is_some_and(|a| !a.is_empty()) {
+                self.warn(&format!("{} are not supported; meshes are shown in their rest pose", name));
+            }
+        }
+
+        // The default scene's root nodes, or every node without a parent when there are no scenes
+        let roots: Vec<usize> = match doc.get("scenes").and_then(Json::as_array) {
+            Some(scenes) if !scenes.is_empty() => {
+                let scene = item(doc, "scenes", index(doc, "scene").unwrap_or(0))?;
+                scene.get("nodes").and_then(Json::as_array).unwrap_or_default().iter().filter_map(Json::as_usize).collect()
+            }
+            _ => {
+                let nodes = doc.get("nodes").and_then(Json::as_array).unwrap_or_default();
+                let children: HashSet<usize> = nodes
+                    .iter()
+                    .flat_map(|n| n.get("children").and_then(Json::as_array).unwrap_or_default())
+                    .filter_map(Json::as_usize)
+                    .collect();
+                (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+            }
+        };
+        for root in roots {
+            self.node(root, &Transform::identity(), 0)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Scene {
+        let world = BvhNode::new(self.world);
+        if !self.has_camera {
+            // Frame everything from the front and a little above
+            let bbox = world.bounding_box();
+            if !bbox.is_empty() {
+                let center = bbox.centroid();
+                let radius = (0.5 * bbox.diagonal().length()).max(1e-3);
+                self.camera.vfov = 40.0;
+                let distance = 1.1 * radius / (self.camera.vfov.to_radians() / 2.0).sin();
+                self.camera.lookat = center;
+                self.camera.lookfrom = center + distance * Vec3::new(0.0, 0.3, 1.0).unit_vector();
+                self.camera.vup = Vec3::new(0.0, 1.0, 0.0);
+            }
+        }
+        // Lit scenes are shown against black, unlit ones under the sky so they aren't empty
+        if !self.camera.lights.is_empty() {
+            self.camera.background = Some(Color::new(0.0, 0.0, 0.0));
+        }
+        Scene { world: HittableList::new(vec![Arc::new(world)]), camera: self.camera }
+    }
+
+    // Node transform relative to its parent
+    fn local_transform(&mut self, node: &Json) -> Result<Option<Transform>> {
+        let m = if let Some(matrix) = node.get("matrix") {
+            let Some(a) = matrix.as_f64_vec().filter(|a| a.len() == 16) else {
+                return error("node \"matrix\" must be an array of 16 numbers".to_string());
+            };
+            // Stored by columns
+            let mut m = [[0.0; 4]; 4];
+            for (col, column) in a.chunks(4).enumerate() {
+                for (row, &value) in column.iter().enumerate() {
+                    m[row][col] = value;
+                }
+            }
+            m
+        } else {
+            let t = numbers(node, "translation", &[0.0; 3])?;
+            let q = numbers(node, "rotation", &[0.0, 0.0, 0.0, 1.0])?;
+            let s = numbers(node, "scale", &[1.0; 3])?;
+            let length = q.iter().map(|c| c * c).sum::<f64>().sqrt();
+            let (x, y, z, w) = if length > 0.0 { (q[0] / length, q[1] / length, q[2] / length, q[3] / length) } else { (0.0, 0.0, 0.0, 1.0) };
+            let r = [
+                [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+                [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+                [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+            ];
+            let mut m = [[0.0, 0.0, 0.0, 1.0]; 4];
+            for row in 0..3 {
+                for col in 0..3 {
+                    m[row][col] = r[row][col] * s[col];
+                }
+                m[row][3] = t[row];
+            }
+            m[3] = [0.0, 0.0, 0.0, 1.0];
+            m
+        };
+        Ok(Transform::from_matrix(m))
+    }
+
+    fn node(&mut self, i: usize, parent: &Transform, depth: usize) -> Result<()> {
+        if depth > 256 {
+            return error("node hierarchy is cyclic or too deep".to_string());
+        }
+        let node = item(self.doc, "nodes", i)?;
+        // Nodes scaled to nothing are a common way to hide things
+        let Some(local) = self.local_transform(node)? else { return Ok(()) };
+        let t = local.then(parent);
+
+        if let Some(mesh) = index(node, "mesh") {
+            self.place_mesh(mesh, &t)?;
+        }
+        if let Some(camera) = index(node, "camera") {
+            self.camera_at(camera, &t)?;
+        }
+        if let Some(light) = extension(node, "KHR_lights_punctual").and_then(|l| index(l, "light")) {
+            self.light_at(light, &t)?;
+        }
+        for child in node.get("children").and_then(Json::as_array).unwrap_or_default() {
+            let Some(child) = child.as_usize() else { return error(format!("node {} has a bad child index", i)) };
+            self.node(child, &t, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn camera_at(&mut self, i: usize, t: &Transform) -> Result<()> {
+        // The first camera in the hierarchy is the one rendered from
+        if self.has_camera {
+            return Ok(());
+        }
+        let camera = item(self.doc, "cameras", i)?;
+        let settings = match camera.get("type").and_then(Json::as_str) {
+            Some("perspective") => camera.get("perspective"),
+            _ => {
+                self.warn("only perspective cameras are supported; camera ignored");
+                return Ok(());
+            }
+        };
+        let Some(settings) = settings else { return error(format!("camera {} has no perspective settings", i)) };
+        self.has_camera = true;
+
+        let c = &mut self.camera;
+        c.vfov = number(settings, "yfov", 0.8).to_degrees();
+        if let Some(aspect) = settings.get("aspectRatio").and_then(Json::as_f64).filter(|&a| a > 0.0) {
+            c.set_image_size(DEFAULT_WIDTH, ((DEFAULT_WIDTH as f64 / aspect).round() as i32).max(1));
+        }
+        c.lookfrom = t.point(&Point3::new(0.0, 0.0, 0.0));
+        c.lookat = c.lookfrom + t.vector(&Vec3::new(0.0, 0.0, -1.0)).unit_vector();
+        c.vup = t.vector(&Vec3::new(0.0, 1.0, 0.0));
+        Ok(())
+    }
+
+    fn light_at(&mut self, i: usize, t: &Transform) -> Result<()> {
+        let lights = extension(self.doc, "KHR_lights_punctual").and_then(|l| l.get("lights")).and_then(Json::as_array);
+        let Some(light) = lights.and_then(|l| l.get(i)) else { return error(format!("reference to light {}, which doesn't exist", i)) };
+        let intensity = color(&numbers(light, "color", &[1.0; 3])?) * number(light, "intensity", 1.0);
+        let position = t.point(&Point3::new(0.0, 0.0, 0.0));
+        let direction = t.vector(&Vec3::new(0.0, 0.0, -1.0));
+
+        let light: Arc<dyn Light> = match light.get("type").and_then(Json::as_str) {
+            Some("point") => Arc::new(PointLight::new(position, intensity)),
+            Some("spot") => {
+                let spot = light.get("spot").unwrap_or(&Json::Null);
+                let outer = number(spot, "outerConeAngle", PI / 4.0).to_degrees();
+                let inner = number(spot, "innerConeAngle", 0.0).to_degrees();
+                Arc::new(SpotLight::new(position, direction, intensity, outer, inner))
+            }
+            Some("directional") => Arc::new(DirectionalLight::new(direction, intensity)),
+            _ => return error(format!("light {} has an unknown type", i)),
+        };
+        self.camera.lights.push(light);
+        Ok(())
+    }
+
+    fn place_mesh(&mut self, i: usize, t: &Transform) -> Result<()> {
+        let primitives = match self.meshes.get(&i) {
+            Some(primitives) => primitives.clone(),
+            None => {
+                let primitives = Arc::new(self.mesh(i)?);
+                self.meshes.insert(i, primitives.clone());
+                primitives
+            }
+        };
+
+        for primitive in primitives.iter() {
+            let Some((emit, two_sided)) = primitive.surface.light else {
+                self.world.push(Arc::new(Instance::new(primitive.shape.clone(), *t)));
+                continue;
+            };
+
+            // Lights are built in world space, a triangle at a time, so each can be sampled
+            let source = &primitive.mesh;
+            let mut mesh = TriangleMesh::new(source.positions.clone(), source.indices.clone())
+                .with_normals(source.normals.clone())
+                .with_uvs(source.uvs.clone())
+                .with_colors(source.colors.clone());
+            mesh.transform(t);
+            // Mirroring transforms keep the front side of the mirrored surface
+            if t.determinant() < 0.0 {
+                mesh.flip_winding();
+            }
+            let mesh = Arc::new(mesh);
+            let mat = Some(primitive.surface.material.clone());
+            let mut lights: Vec<Arc<dyn Hittable>> = Vec::new();
+            for face in 0..mesh.indices.len() {
+                let triangle = Arc::new(Triangle::new(mesh.clone(), face, mat.clone()));
+                let light = if two_sided { DiffuseAreaLight::two_sided(triangle, emit) } else { DiffuseAreaLight::new(triangle, emit) };
+                self.camera.lights.push(light.clone());
+                lights.push(light);
+            }
+            self.world.push(Arc::new(BvhNode::new(lights)));
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, i: usize) -> Result<Vec<Primitive>> {
+        let doc = self.doc;
+        let mesh = item(doc, "meshes", i)?;
+        let mut primitives = Vec::new();
+        for p in mesh.get("primitives").and_then(Json::as_array).unwrap_or_default() {
+            let Some(attributes) = p.get("attributes") else { return error(format!("mesh {} has a primitive without attributes", i)) };
+            let Some(position) = index(attributes, "POSITION") else { continue };
+            let positions: Vec<Point3> = self.accessor(position, 3)?.chunks(3).map(|c| Point3::new(c[0], c[1], c[2])).collect();
+            let n = positions.len();
+
+            let vertices: Vec<usize> = match index(p, "indices") {
+                Some(a) => self.accessor(a, 1)?.into_iter().map(|v| v as usize).collect(),
+                None => (0..n).collect(),
+            };
+            if vertices.iter().any(|&v| v >= n) {
+                return error(format!("mesh {} has vertex indices past its last vertex", i));
+            }
+            let indices: Vec<[usize; 3]> = match index(p, "mode").unwrap_or(4) {
+                4 => vertices.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                // Every other triangle of a strip is reversed to keep the winding
+                5 => (0..vertices.len().saturating_sub(2))
+                    .map(|k| if k % 2 == 0 { [vertices[k], vertices[k + 1], vertices[k + 2]] } else { [vertices[k + 1], vertices[k], vertices[k + 2]] })
+                    .collect(),
+                6 => (1..vertices.len().saturating_sub(1)).map(|k| [vertices[k], vertices[k + 1], vertices[0]]).collect(),
+                _ => {
+                    self.warn("point and line primitives are not supported and were skipped");
+                    continue;
+                }
+            };
+            if indices.is_empty() {
+                continue;
+            }
+
+            let mut mesh = TriangleMesh::new(positions, indices);
+            if let Some(normal) = index(attributes, "NORMAL") {
+                let normals = self.accessor(normal, 3)?;
+                if normals.len() != 3 * n {
+                    return error(format!("mesh {} needs one normal per vertex", i));
+                }
+                mesh = mesh.with_normals(normals.chunks(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect());
+            }
+            if let Some(uv) = index(attributes, "TEXCOORD_0") {
+                let uvs = self.accessor(uv, 2)?;
+                if uvs.len() != 2 * n {
+                    return error(format!("mesh {} needs one texture coordinate per vertex", i));
+                }
+                // glTF's v runs down the image
+                mesh = mesh.with_uvs(uvs.chunks(2).map(|c| (c[0], 1.0 - c[1])).collect());
+            }
+
+            let surface = self.material(index(p, "material"))?;
+            let mesh = Arc::new(mesh);
+            let mut shape: Arc<dyn Hittable> = Arc::new(make_mesh(mesh.clone(), Some(surface.material.clone())));
+            if let Some(alpha) = &surface.alpha {
+                shape = Arc::new(AlphaMask::new(shape, alpha.clone()));
+            }
+            primitives.push(Primitive { mesh, surface, shape });
+        }
+        Ok(primitives)
+    }
+
+    // Elements of an accessor as numbers, with normalized integers scaled to [0,1] or [-1,1]
+    fn accessor(&self, i: usize, components: usize) -> Result<Vec<f64>> {
+        let accessor = item(self.doc, "accessors", i)?;
+        let expected = match components {
+            1 => "SCALAR",
+            2 => "VEC2",
+            3 => "VEC3",
+            _ => "VEC4",
+        };
+        if accessor.get("type").and_then(Json::as_str) != Some(expected) {
+            return error(format!("accessor {} should be of type {}", i, expected));
+        }
+        if accessor.get("sparse").is_some() {
+            return error(format!("accessor {} is sparse, which isn't supported", i));
+        }
+        let count = index(accessor, "count").unwrap_or(0);
+        let Some(view) = index(accessor, "bufferView") else { return Ok(vec![0.0; count * components]) };
+
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+        let (size, scale) = match index(accessor, "componentType") {
+            Some(5120) => (1, 127.0),
+            Some(5121) => (1, 255.0),
+            Some(5122) => (2, 32767.0),
+            Some(5123) => (2, 65535.0),
+            Some(5125) => (4, 1.0),
+            Some(5126) => (4, 1.0),
+            _ => return error(format!("accessor {} has an unknown component type", i)),
+        };
+        let component_type = index(accessor, "componentType").unwrap();
+
+        let (data, stride) = self.buffer_view(view)?;
+        let start = index(accessor, "byteOffset").unwrap_or(0);
+        let stride = stride.unwrap_or(size * components);
+        if count > 0 && start + (count - 1) * stride + size * components > data.len() {
+            return error(format!("accessor {} reaches past the end of its buffer view", i));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for c in 0..components {
+                let at = start + element * stride + c * size;
+                let b = &data[at..at + size];
+                let value = match component_type {
+                    5120 => b[0] as i8 as f64,
+                    5121 => b[0] as f64,
+                    5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                };
+                values.push(if normalized && component_type != 5126 { (value / scale).max(-1.0) } else { value });
+            }
+        }
+        Ok(values)
+    }
+
+    // Bytes of a buffer view and its stride, if it has one
+    fn buffer_view(&self, i: usize) -> Result<(&[u8], Option<usize>)> {
+        let view = item(self.doc, "bufferViews", i)?;
+        let buffer = index(view, "buffer").and_then(|b| self.buffers.get(b));
+        let start = index(view, "byteOffset").unwrap_or(0);
+        let length = index(view, "byteLength").unwrap_or(0);
+        match buffer.and_then(|b| b.get(start..start + length)) {
+            Some(data) => Ok((data, index(view, "byteStride"))),
+            None => error(format!("buffer view {} lies outside its buffer", i)),
+        }
+    }
+
+    fn image(&mut self, i: usize) -> Result<Option<Arc<PngImage>>> {
+        if let Some(image) = self.images.get(&i) {
+            return Ok(image.clone());
+        }
+        let image = item(self.doc, "images", i)?;
+        let bytes = match (image.get("uri").and_then(Json::as_str), index(image, "bufferView")) {
+            (Some(uri), _) => read_uri(&self.base_dir, uri)?,
+            (None, Some(view)) => self.buffer_view(view)?.0.to_vec(),
+            _ => return error(format!("image {} has no data", i)),
+        };
+        let decoded = if bytes.starts_with(b"\x89PNG") {
+            match PngImage::decode(&bytes) {
+                Ok(png) => Some(Arc::new(png)),
+                Err(e) => {
+                    self.warn(&format!("can't decode image {}: {}; texture ignored", i, e));
+                    None
+                }
+            }
+        } else {
+            self.warn("only PNG textures are supported; other images are ignored");
+            None
+        };
+        self.images.insert(i, decoded.clone());
+        Ok(decoded)
+    }
+
+    // Texture from a material's texture info, or None when it has none or it can't be read
+    fn texture(&mut self, info: Option<&Json>, decode: Decode) -> Result<Option<Arc<dyn Texture>>> {
+        let Some(info) = info else { return Ok(None) };
+        let Some(i) = index(info, "index") else { return error("texture reference without an index".to_string()) };
+        if index(info, "texCoord").unwrap_or(0) != 0 {
+            self.warn("only the first set of texture coordinates is supported");
+        }
+        if extension(info, "KHR_texture_transform").is_some() {
+            self.warn("texture transforms are not supported");
+        }
+        if let Some(texture) = self.textures.get(&(i, decode)) {
+            return Ok(texture.clone());
+        }
+
+        let source = index(item(self.doc, "textures", i)?, "source");
+        let texture = match source {
+            Some(source) => self.image(source)?.map(|png| {
+                let image = match decode {
+                    Decode::Srgb => png.color(true),
+                    Decode::Linear => png.color(false),
+                    Decode::Alpha => png.alpha(),
+                };
+                Arc::new(ImageTexture::new(image)) as Arc<dyn Texture>
+            }),
+            None => {
+                self.warn("textures without a PNG source are ignored");
+                None
+            }
+        };
+        self.textures.insert((i, decode), texture.clone());
+        Ok(texture)
+    }
+
+    fn material(&mut self, i: Option<usize>) -> Result<Arc<SurfaceMaterial>> {
+        if let Some(surface) = self.materials.get(&i) {
+            return Ok(surface.clone());
+        }
+        let surface = Arc::new(match i {
+            Some(i) => self.build_material(item(self.doc, "materials", i)?)?,
+            // glTF's default material
+            None => SurfaceMaterial {
+                material: Arc::new(Principled::new(Color::new(1.0, 1.0, 1.0)).with_metallic(1.0).with_roughness(1.0)),
+                alpha: None,
+                light: None,
+            },
+        });
+        self.materials.insert(i, surface.clone());
+        Ok(surface)
+    }
+
+    fn build_material(&mut self, m: &Json) -> Result<SurfaceMaterial> {
+        let pbr = m.get("pbrMetallicRoughness").unwrap_or(&Json::Null);
+        let base_factor = numbers(pbr, "baseColorFactor", &[1.0; 4])?;
+        let base_texture = self.texture(pbr.get("baseColorTexture"), Decode::Srgb)?;
+        let base_color: Arc<dyn Texture> = match &base_texture {
+            Some(tex) => Arc::new(Factored { tex: tex.clone(), channel: None, factor: color(&base_factor) }),
+            None => Arc::new(SolidColor::new(color(&base_factor))),
+        };
+        let mut principled = Principled::from_texture(base_color);
+
+        // Metalness is in the blue channel and roughness in the green one
+        let metallic = number(pbr, "metallicFactor", 1.0);
+        let roughness = number(pbr, "roughnessFactor", 1.0);
+        principled = principled.with_metallic(metallic).with_roughness(roughness);
+        if let Some(tex) = self.texture(pbr.get("metallicRoughnessTexture"), Decode::Linear)? {
+            principled.metallic = Arc::new(Factored { tex: tex.clone(), channel: Some(2), factor: Color::new(1.0, 1.0, 1.0) * metallic });
+            principled.roughness = Arc::new(Factored { tex, channel: Some(1), factor: Color::new(1.0, 1.0, 1.0) * roughness });
+        }
+
+        if let Some(ior) = extension(m, "KHR_materials_ior") {
+            let ior = number(ior, "ior", 1.5);
+            // Specular is measured in 8% steps of normal incidence reflectance
+            let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+            principled = principled.with_specular(f0 / 0.08, 0.0);
+            principled.ior = Arc::new(SolidColor::scalar(ior));
+        }
+        if let Some(transmission) = extension(m, "KHR_materials_transmission") {
+            let factor = number(transmission, "transmissionFactor", 0.0);
+            principled.transmission = match self.texture(transmission.get("transmissionTexture"), Decode::Linear)? {
+                Some(tex) => Arc::new(Factored { tex, channel: Some(0), factor: Color::new(1.0, 1.0, 1.0) * factor }),
+                None => Arc::new(SolidColor::scalar(factor)),
+            };
+        }
+
+        let strength = extension(m, "KHR_materials_emissive_strength").map_or(1.0, |s| number(s, "emissiveStrength", 1.0));
+        let emissive = color(&numbers(m, "emissiveFactor", &[0.0; 3])?) * strength;
+        let double_sided = m.get("doubleSided").and_then(Json::as_bool).unwrap_or(false);
+        let mut light = None;
+        if emissive.max_component() > 0.0 {
+            match self.texture(m.get("emissiveTexture"), Decode::Srgb)? {
+                Some(tex) => principled.emission = Arc::new(Factored { tex, channel: None, factor: emissive }),
+                None => light = Some((emissive, double_sided)),
+            }
+        }
+
+        let mut material: Arc<dyn Material> = Arc::new(principled);
+        if let Some(normals) = self.texture(m.get("normalTexture"), Decode::Linear)? {
+            material = Arc::new(Bumped::normal_map(material, normals));
+        }
+
+        let alpha_factor = base_factor[3];
+        let alpha_mode = m.get("alphaMode").and_then(Json::as_str).unwrap_or("OPAQUE");
+        let alpha = if alpha_mode == "OPAQUE" {
+            None
+        } else {
+            let opacity: Arc<dyn Texture> = match pbr.get("baseColorTexture") {
+                Some(info) if base_texture.is_some() => {
+                    let tex = self.texture(Some(info), Decode::Alpha)?.expect("the color of this image was read");
+                    Arc::new(Factored { tex, channel: Some(0), factor: Color::new(1.0, 1.0, 1.0) * alpha_factor })
+                }
+                _ => Arc::new(SolidColor::scalar(alpha_factor)),
+            };
+            match alpha_mode {
+                "MASK" => Some(Arc::new(Threshold { tex: opacity, cutoff: number(m, "alphaCutoff", 0.5) }) as Arc<dyn Texture>),
+                _ => Some(opacity),
+            }
+        };
+
+        Ok(SurfaceMaterial { material, alpha, light })
+    }
+}
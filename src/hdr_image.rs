@@ -0,0 +1,239 @@
+use crate::png::PngImage;
+use crate::rtweekend::*;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+// A floating point RGB image, stored row by row starting from the top-left pixel.
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, data: Vec<Color>) -> Self {
+        assert_eq!(data.len(), width * height);
+        Self { width, height, data }
+    }
+
+    // Loads a Radiance .hdr, a .pfm or a .png file, picked by extension. PNG colors are taken
+    // to be sRGB and decoded to linear.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match ext.as_str() {
+            "hdr" | "pic" => Self::from_radiance(&bytes),
+            "pfm" => Self::from_pfm(&bytes),
+            "png" => Ok(PngImage::decode(&bytes)?.color(true)),
+            _ => Err(invalid("unsupported image extension (expected .hdr, .pfm or .png)")),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let x = x.min(self.width - 1);
+        let y = y.min(self.height - 1);
+        self.data[y * self.width + x]
+    }
+
+    // Radiance RGBE, either flat or with new-style run-length encoded scanlines.
+    pub fn from_radiance(bytes: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let next_line = |pos: &mut usize| -> Result<String> {
+            let start = *pos;
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+            if *pos >= bytes.len() {
+                return Err(invalid("unexpected end of Radiance header"));
+            }
+            let line = String::from_utf8_lossy(&bytes[start..*pos]).trim().to_string();
+            *pos += 1;
+            Ok(line)
+        };
+
+        let magic = next_line(&mut pos)?;
+        if !magic.starts_with("#?") {
+            return Err(invalid("missing Radiance #? signature"));
+        }
+        loop {
+            let line = next_line(&mut pos)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid("only the 32-bit_rle_rgbe Radiance format is supported"));
+                }
+            }
+        }
+
+        let resolution = next_line(&mut pos)?;
+        let tokens: Vec<&str> = resolution.split_whitespace().collect();
+        if tokens.len() != 4 || tokens[2] != "+X" || (tokens[0] != "-Y" && tokens[0] != "+Y") {
+            return Err(invalid("unsupported Radiance resolution line"));
+        }
+        let height: usize = tokens[1].parse().map_err(|_| invalid("bad image height"))?;
+        let width: usize = tokens[3].parse().map_err(|_| invalid("bad image width"))?;
+        if width == 0 || height == 0 {
+            return Err(invalid("empty Radiance image"));
+        }
+
+        let mut data = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            Self::read_rgbe_scanline(bytes, &mut pos, &mut scanline)?;
+            data.extend(scanline.iter().map(|rgbe| Self::rgbe_to_color(*rgbe)));
+        }
+
+        // "+Y" images are stored bottom-up
+        if tokens[0] == "+Y" {
+            let rows: Vec<Vec<Color>> = data.chunks(width).rev().map(|row| row.to_vec()).collect();
+            data = rows.concat();
+        }
+
+        Ok(Self::new(width, height, data))
+    }
+
+    fn read_rgbe_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<()> {
+        let width = scanline.len();
+        let take = |pos: &mut usize| -> Result<u8> {
+            let b = *bytes.get(*pos).ok_or_else(|| invalid("unexpected end of Radiance pixel data"))?;
+            *pos += 1;
+            Ok(b)
+        };
+
+        let is_rle = (8..0x8000).contains(&width)
+            && bytes.len() >= *pos + 4
+            && bytes[*pos] == 2
+            && bytes[*pos + 1] == 2
+            && bytes[*pos + 2] & 0x80 == 0;
+
+        if !is_rle {
+            for px in scanline.iter_mut() {
+                for c in px.iter_mut() {
+                    *c = take(pos)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let encoded_width = ((bytes[*pos + 2] as usize) << 8) | bytes[*pos + 3] as usize;
+        if encoded_width != width {
+            return Err(invalid("Radiance scanline width mismatch"));
+        }
+        *pos += 4;
+
+        // Each of the four channels is run-length encoded separately
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = take(pos)? as usize;
+                if count > 128 {
+                    let run = count - 128;
+                    let value = take(pos)?;
+                    if x + run > width {
+                        return Err(invalid("Radiance run overflows scanline"));
+                    }
+                    for px in &mut scanline[x..x + run] {
+                        px[channel] = value;
+                    }
+                    x += run;
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err(invalid("bad Radiance literal run"));
+                    }
+                    for px in &mut scanline[x..x + count] {
+                        px[channel] = take(pos)?;
+                    }
+                    x += count;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+        if rgbe[3] == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let f = 2f64.powi(rgbe[3] as i32 - 136);
+        Color::new(
+            (rgbe[0] as f64 + 0.5) * f,
+            (rgbe[1] as f64 + 0.5) * f,
+            (rgbe[2] as f64 + 0.5) * f,
+        )
+    }
+
+    // Portable float map, "PF" (RGB) or "Pf" (greyscale). A negative scale means little endian.
+    pub fn from_pfm(bytes: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let mut tokens = Vec::new();
+        while tokens.len() < 4 {
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("unexpected end of PFM header"));
+            }
+            tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+        }
+        // exactly one whitespace character separates the header from the raster
+        pos += 1;
+
+        let channels = match tokens[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("missing PFM signature")),
+        };
+        let width: usize = tokens[1].parse().map_err(|_| invalid("bad PFM width"))?;
+        let height: usize = tokens[2].parse().map_err(|_| invalid("bad PFM height"))?;
+        let scale: f64 = tokens[3].parse().map_err(|_| invalid("bad PFM scale"))?;
+        let little_endian = scale < 0.0;
+
+        let needed = width * height * channels * 4;
+        if width == 0 || height == 0 || bytes.len() < pos + needed {
+            return Err(invalid("truncated PFM raster"));
+        }
+
+        let read_f32 = |i: usize| -> f64 {
+            let b = [bytes[pos + 4 * i], bytes[pos + 4 * i + 1], bytes[pos + 4 * i + 2], bytes[pos + 4 * i + 3]];
+            let v = if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) };
+            v as f64
+        };
+
+        let mut data = vec![Color::new(0.0, 0.0, 0.0); width * height];
+        for y in 0..height {
+            // PFM rows run from the bottom of the image to the top
+            let row = height - 1 - y;
+            for x in 0..width {
+                let i = (y * width + x) * channels;
+                data[row * width + x] = if channels == 3 {
+                    Color::new(read_f32(i), read_f32(i + 1), read_f32(i + 2))
+                } else {
+                    let v = read_f32(i);
+                    Color::new(v, v, v)
+                };
+            }
+        }
+
+        Ok(Self::new(width, height, data))
+    }
+}
@@ -1,26 +1,57 @@
 use crate::rtweekend::*;
+use crate::aabb::Aabb;
+use crate::light::Light;
 use crate::material::Material;
 use crate::interval::Interval;
 use crate::ray::*;
 use crate::vec3::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct HitRecord {
     pub p: Point3,
+    // Shading normal, which materials use for their local frame. It starts out equal to the
+    // geometric normal and may be perturbed by normal or bump maps.
     pub normal: Vec3,
-    pub mat: Option<Rc<dyn Material>>,
+    // True surface normal, on the same side as the incoming ray
+    pub geometric_normal: Vec3,
+    pub mat: Option<Arc<dyn Material>>,
+    // Set when the surface hit is itself an area light
+    pub light: Option<Arc<dyn Light>>,
     pub t: f64,
+    // Surface coordinates of the hit point, for texture lookups
+    pub u: f64,
+    pub v: f64,
+    // Partial derivatives of the hit point with respect to u and v, or zero if the shape has
+    // no parameterization
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // Vertex color blended across the face, on meshes that have them
+    pub color: Option<Color>,
     pub front_face: bool,
 }
 
 impl HitRecord {
     pub fn new(p: Point3, normal: Vec3, t: f64, front_face: bool) -> Self {
-        Self { p, normal, mat: None, t, front_face }
+        Self {
+            p,
+            normal,
+            geometric_normal: normal,
+            mat: None,
+            light: None,
+            t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            color: None,
+            front_face,
+        }
     }
 
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(&outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal };
+        self.geometric_normal = self.normal;
     }
 }
 
@@ -29,14 +60,63 @@ impl Clone for HitRecord {
         Self {
             p: self.p,
             normal: self.normal,
+            geometric_normal: self.geometric_normal,
             mat: self.mat.clone(),
+            light: self.light.clone(),
             t: self.t,
+            u: self.u,
+            v: self.v,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+            color: self.color,
             front_face: self.front_face,
         }
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+pub trait Hittable: Send + Sync {
+    // Closest hit within `ray_t`. Shapes that decide hits at random, such as media, draw from
+    // `sampler`.
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // Solid angle density of random() producing `direction` from `origin`
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Random direction from `origin` towards a point on the object
+    fn random(&self, _origin: &Point3, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    fn surface_area(&self) -> f64 {
+        0.0
+    }
+
+    // Axis and cosine of the half-angle of a cone bounding the outward surface normals
+    fn normal_cone(&self) -> (Vec3, f64) {
+        (Vec3::new(0.0, 0.0, 1.0), -1.0)
+    }
 }
 
+// Closest hit on `object` within `ray_t` that `reject` doesn't turn down. The search resumes
+// just past each rejected hit, since some shapes accept hits at the interval's ends.
+pub fn hit_skipping(
+    object: &dyn Hittable,
+    r: &Ray,
+    ray_t: Interval,
+    rec: &mut HitRecord,
+    sampler: &mut Sampler,
+    mut reject: impl FnMut(&HitRecord, &mut Sampler) -> bool,
+) -> bool {
+    let mut t_min = ray_t.min;
+    while object.hit(r, Interval::new(t_min, ray_t.max), rec, sampler) {
+        if !reject(rec, sampler) {
+            return true;
+        }
+        t_min = rec.t + 1e-6;
+    }
+    false
+}
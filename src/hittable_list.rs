@@ -1,29 +1,34 @@
+use crate::aabb::Aabb;
 use crate::hittable::*;
 use crate::interval::Interval;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct HittableList {
-    objects: Vec<Rc<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
-    pub fn new(objects: Vec<Rc<dyn Hittable>>) -> Self {
-        HittableList { objects }
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Self {
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, object| Aabb::surrounding(&bbox, &object.bounding_box()));
+        HittableList { objects, bbox }
     }
 
-    pub fn add(&mut self, object: Rc<dyn Hittable>) {
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
 
-    pub fn clear(&mut self) {
-        self.objects.clear();
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
         let mut temp_rec = HitRecord::new(
             Point3::new(0.0, 0.0, 0.0),
             Vec3::new(0.0, 0.0, 0.0),
@@ -34,7 +39,9 @@ impl Hittable for HittableList {
         let mut closest_so_far = ray_t.max;
 
         for object in &self.objects {
-            if object.hit(r, Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+            // Only area lights set this, so clear whatever an earlier object left behind
+            temp_rec.light = None;
+            if object.hit(r, Interval::new(ray_t.min, closest_so_far), &mut temp_rec, sampler) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
                 *rec = temp_rec.clone();
@@ -43,4 +50,8 @@ impl Hittable for HittableList {
 
         hit_anything
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
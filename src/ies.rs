@@ -0,0 +1,178 @@
+use std::cell::Cell;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+// Angular intensity distribution of a luminaire, read from an IES LM-63 photometric file.
+//
+// Only type C photometry is supported, which covers nearly all architectural fixtures. Vertical
+// angles are measured from the nadir (straight down, 0 degrees) and horizontal angles around it.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // candela[h][v], already scaled by the file's candela multiplier
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+impl IesProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        Self::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        // Skip the keyword header up to and including the TILT line
+        let mut lines = text.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) => {
+                    if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                        break tilt.trim().to_string();
+                    }
+                }
+                None => return Err(invalid("missing TILT= line in IES file")),
+            }
+        };
+
+        let tokens: Vec<&str> = lines.flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ',')).filter(|t| !t.is_empty()).collect();
+        let pos = Cell::new(0);
+        let next = || -> Result<f64> {
+            let token = tokens.get(pos.get()).ok_or_else(|| invalid("unexpected end of IES data"))?;
+            pos.set(pos.get() + 1);
+            token.parse::<f64>().map_err(|_| invalid(&format!("bad number '{}' in IES data", token)))
+        };
+
+        // Lamp tilt data only matters for fixtures that are mounted at an angle; it is skipped
+        if tilt == "INCLUDE" {
+            let _lamp_to_luminaire_geometry = next()?;
+            let pairs = next()? as usize;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let n_vertical = next()? as usize;
+        let n_horizontal = next()? as usize;
+        let photometric_type = next()? as i32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let _ballast_factor = next()?;
+        let _future_use = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(invalid("only type C IES photometry is supported"));
+        }
+        if n_vertical == 0 || n_horizontal == 0 {
+            return Err(invalid("IES file has no candela values"));
+        }
+        // Counts are checked against the data left before anything is allocated for them
+        let needed = n_vertical.checked_mul(n_horizontal).and_then(|n| n.checked_add(n_vertical)?.checked_add(n_horizontal));
+        if needed.is_none_or(|n| n > tokens.len() - pos.get()) {
+            return Err(invalid("IES angle counts don't match the data"));
+        }
+
+        let vertical_angles = (0..n_vertical).map(|_| next()).collect::<Result<Vec<f64>>>()?;
+        let horizontal_angles = (0..n_horizontal).map(|_| next()).collect::<Result<Vec<f64>>>()?;
+        let mut candela = Vec::with_capacity(n_horizontal);
+        for _ in 0..n_horizontal {
+            let row = (0..n_vertical).map(|_| next().map(|c| c * multiplier)).collect::<Result<Vec<f64>>>()?;
+            candela.push(row);
+        }
+
+        if vertical_angles.windows(2).any(|w| w[1] < w[0]) || horizontal_angles.windows(2).any(|w| w[1] < w[0]) {
+            return Err(invalid("IES angles must be increasing"));
+        }
+
+        let max_candela = candela.iter().flatten().fold(0.0f64, |m, &c| m.max(c));
+        Ok(Self { vertical_angles, horizontal_angles, candela, max_candela })
+    }
+
+    // Folds a horizontal angle into the range the file covers, following its symmetry.
+    fn fold_horizontal(&self, c: f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = *self.horizontal_angles.last().unwrap();
+        let c = c.rem_euclid(360.0);
+        if last <= 0.0 {
+            0.0
+        } else if first >= 90.0 {
+            // 90 to 270 degrees, mirrored across the plane through 90 and 270
+            if c < 90.0 {
+                180.0 - c
+            } else if c > 270.0 {
+                540.0 - c
+            } else {
+                c
+            }
+        } else if last <= 90.0 {
+            let c = if c > 180.0 { 360.0 - c } else { c };
+            if c > 90.0 { 180.0 - c } else { c }
+        } else if last <= 180.0 {
+            if c > 180.0 { 360.0 - c } else { c }
+        } else {
+            c
+        }
+    }
+
+    // Index of the interval containing x and the interpolation weight within it.
+    fn locate(angles: &[f64], x: f64) -> Option<(usize, f64)> {
+        if angles.len() == 1 {
+            return Some((0, 0.0));
+        }
+        if x < angles[0] || x > angles[angles.len() - 1] {
+            return None;
+        }
+        let i = angles.partition_point(|&a| a <= x).clamp(1, angles.len() - 1) - 1;
+        let span = angles[i + 1] - angles[i];
+        let t = if span > 0.0 { (x - angles[i]) / span } else { 0.0 };
+        Some((i, t))
+    }
+
+    // Intensity in candela at vertical angle `gamma` and horizontal angle `c`, in degrees.
+    pub fn candela(&self, gamma: f64, c: f64) -> f64 {
+        let c = self.fold_horizontal(c);
+        let (iv, tv) = match Self::locate(&self.vertical_angles, gamma) {
+            Some(v) => v,
+            None => return 0.0,
+        };
+        // A horizontal angle outside the ones given lies between the last and, wrapping around, the
+        // first, as in full 360 data that doesn't repeat 0 as 360
+        let (ih, ih_far, th) = match Self::locate(&self.horizontal_angles, c) {
+            Some((i, t)) => (i, i + 1, t),
+            None => {
+                let last = self.horizontal_angles.len() - 1;
+                let span = (self.horizontal_angles[0] - self.horizontal_angles[last]).rem_euclid(360.0);
+                let t = if span > 0.0 { (c - self.horizontal_angles[last]).rem_euclid(360.0) / span } else { 0.0 };
+                (last, 0, t)
+            }
+        };
+
+        let at = |h: usize, v: usize| -> f64 {
+            let h = h.min(self.horizontal_angles.len() - 1);
+            let v = v.min(self.vertical_angles.len() - 1);
+            self.candela[h][v]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let near = lerp(at(ih, iv), at(ih, iv + 1), tv);
+        let far = lerp(at(ih_far, iv), at(ih_far, iv + 1), tv);
+        lerp(near, far, th)
+    }
+
+    // Intensity relative to the brightest direction, in [0,1].
+    pub fn relative(&self, gamma: f64, c: f64) -> f64 {
+        if self.max_candela <= 0.0 {
+            return 0.0;
+        }
+        self.candela(gamma, c) / self.max_candela
+    }
+}
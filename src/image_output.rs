@@ -0,0 +1,128 @@
+use crate::color::to_rgb8;
+use crate::hdr_image::HdrImage;
+use std::io::{Result, Write};
+use std::path::Path;
+
+// File formats a rendered image can be written in. PPM and PNG hold gamma-encoded 8-bit
+// colors clamped to [0,1]; PFM keeps the linear floating point radiance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Pfm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(Self::Ppm),
+            "pfm" => Some(Self::Pfm),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        Self::from_name(path.as_ref().extension()?.to_str()?)
+    }
+}
+
+pub fn write_image(out: &mut dyn Write, image: &HdrImage, format: ImageFormat) -> Result<()> {
+    match format {
+        ImageFormat::Ppm => write_ppm(out, image),
+        ImageFormat::Pfm => write_pfm(out, image),
+        ImageFormat::Png => write_png(out, image),
+    }
+}
+
+// Plain-text PPM, the renderer's original output
+fn write_ppm(out: &mut dyn Write, image: &HdrImage) -> Result<()> {
+    writeln!(out, "P3\n{} {}\n255", image.width(), image.height())?;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let [r, g, b] = to_rgb8(image.pixel(x, y));
+            writeln!(out, "{} {} {}", r, g, b)?;
+        }
+    }
+    Ok(())
+}
+
+// Little-endian PFM, whose rows run from the bottom up
+fn write_pfm(out: &mut dyn Write, image: &HdrImage) -> Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    let mut bytes = Vec::with_capacity(image.width() * image.height() * 12);
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            let c = image.pixel(x, y);
+            for v in [c.x(), c.y(), c.z()] {
+                bytes.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+        }
+    }
+    out.write_all(&bytes)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_png_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    out.write_all(&body)?;
+    out.write_all(&crc32(&body).to_be_bytes())
+}
+
+// 8-bit RGB PNG. The pixel data goes in uncompressed deflate blocks, which any decoder reads
+// and which need no compression library.
+fn write_png(out: &mut dyn Write, image: &HdrImage) -> Result<()> {
+    let (width, height) = (image.width(), image.height());
+    let mut raw = Vec::with_capacity(height * (3 * width + 1));
+    for y in 0..height {
+        // Filter type 0: the scanline is stored as is
+        raw.push(0);
+        for x in 0..width {
+            raw.extend_from_slice(&to_rgb8(image.pixel(x, y)));
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(65535).peekable();
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, default compression and filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_png_chunk(out, b"IHDR", &header)?;
+    write_png_chunk(out, b"IDAT", &zlib)?;
+    write_png_chunk(out, b"IEND", &[])
+}
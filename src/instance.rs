@@ -0,0 +1,79 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::rtweekend::*;
+use crate::transform::Transform;
+use std::sync::Arc;
+
+// Places an object in the world through an affine transform, so one object can appear several
+// times at different positions, orientations and sizes without copying it.
+//
+// Rays are taken into the object's space for intersection and the hit is brought back out.
+// Light sampling through an instance assumes the transform preserves angles (rotations,
+// translations and uniform scales); solid angle densities are wrong under shear or non-uniform
+// scaling.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    // Object space to world space
+    transform: Transform,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let object_box = object.bounding_box();
+        let mut bbox = Aabb::EMPTY;
+        if !object_box.is_empty() {
+            let (lo, hi) = (object_box.min(), object_box.max());
+            for corner in 0..8 {
+                let pick = |axis: usize| if corner & (1 << axis) == 0 { lo[axis] } else { hi[axis] };
+                let p = transform.point(&Point3::new(pick(0), pick(1), pick(2)));
+                bbox = Aabb::surrounding(&bbox, &Aabb::from_points(p, p));
+            }
+        }
+        Self { object, transform, bbox }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        let to_object = self.transform.inverse();
+        let object_ray = Ray::new(to_object.point(&r.origin()), to_object.vector(&r.direction())).with_wavelength(r.wavelength());
+        if !self.object.hit(&object_ray, ray_t, rec, sampler) {
+            return false;
+        }
+
+        // The direction is not renormalized, so t is the same along both rays
+        rec.p = self.transform.point(&rec.p);
+        if !rec.normal.near_zero() {
+            rec.normal = self.transform.normal(&rec.normal).unit_vector();
+            rec.geometric_normal = self.transform.normal(&rec.geometric_normal).unit_vector();
+        }
+        rec.dpdu = self.transform.vector(&rec.dpdu);
+        rec.dpdv = self.transform.vector(&rec.dpdv);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let to_object = self.transform.inverse();
+        self.object.pdf_value(&to_object.point(origin), &to_object.vector(direction))
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let to_object = self.transform.inverse();
+        self.transform.vector(&self.object.random(&to_object.point(origin), sampler))
+    }
+
+    fn surface_area(&self) -> f64 {
+        self.object.surface_area() * self.transform.determinant().abs().powf(2.0 / 3.0)
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        let (w, cos_theta) = self.object.normal_cone();
+        (self.transform.normal(&w).unit_vector(), cos_theta)
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -8,10 +9,6 @@ impl Interval {
         Self { min: a, max: b }
     }
 
-    pub fn empty() -> Self {
-        Self { min: f64::INFINITY, max: f64::NEG_INFINITY } // empty interval
-    }
-
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -24,12 +21,20 @@ impl Interval {
         self.min < x && x < self.max
     }
     
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
+
+    pub fn union(a: &Interval, b: &Interval) -> Interval {
+        Interval::new(a.min.min(b.min), a.max.max(b.max))
+    }
+
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min { self.min } else if x > self.max { self.max } else { x }
     }
 
     // define empty and universe intervals
     pub const EMPTY: Interval = Interval { min: f64::INFINITY, max: f64::NEG_INFINITY };
-    pub const UNIVERSE: Interval = Interval { min: f64::NEG_INFINITY, max: f64::INFINITY };
 }
 
@@ -0,0 +1,232 @@
+use crate::fresnel::fr_dielectric;
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::{roughness_to_alpha, same_hemisphere, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::rtweekend::*;
+use crate::texture::{SolidColor, Texture};
+use std::sync::Arc;
+
+// Blend of two materials: `amount` is the weight of `b`, read from the first channel of a
+// texture so it can act as a mask. Each scatter picks one of the two at random, while light
+// sampling sees the weighted sum of both.
+pub struct MixMaterial {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub amount: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, amount: f64) -> Self {
+        Self::with_mask(a, b, Arc::new(SolidColor::scalar(amount)))
+    }
+
+    pub fn with_mask(a: Arc<dyn Material>, b: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        Self { a, b, amount: mask }
+    }
+
+    fn amount(&self, rec: &HitRecord) -> f64 {
+        self.amount.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        match self.sample(r_in, rec, sampler) {
+            Some(srec) => {
+                *attenuation = srec.attenuation;
+                *scattered = srec.scattered;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let t = self.amount(rec);
+        let chosen = if sampler.next_f64() < t { &self.b } else { &self.a };
+        let srec = chosen.sample(r_in, rec, sampler)?;
+        // A specular sample is weighted by the selection probability alone, which cancels out
+        if srec.pdf == 0.0 {
+            return Some(srec);
+        }
+
+        let wi = srec.scattered.direction();
+        let pdf = self.pdf(r_in, rec, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord { attenuation: self.eval(r_in, rec, &wi) / pdf, scattered: srec.scattered, pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let t = self.amount(rec);
+        (1.0 - t) * self.a.eval(r_in, rec, wi) + t * self.b.eval(r_in, rec, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let t = self.amount(rec);
+        (1.0 - t) * self.a.pdf(r_in, rec, wi) + t * self.b.pdf(r_in, rec, wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let t = self.amount(rec);
+        (1.0 - t) * self.a.emitted(r_in, rec) + t * self.b.emitted(r_in, rec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+}
+
+// A clear dielectric coat over any base material, like varnish on wood or lacquer on paint.
+//
+// The coat reflects its Fresnel share off the top, smooth or over a GGX distribution. The rest
+// goes through it twice, on the way to and back from the base, and is tinted by the coat's
+// color along each pass. Directions are not bent by the coat, which keeps the base's own
+// sampling usable.
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    ior: f64,
+    distribution: TrowbridgeReitz,
+    // Transmittance of one pass through the coat at normal incidence
+    tint: Color,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            ior,
+            distribution: TrowbridgeReitz::isotropic(roughness_to_alpha(roughness)),
+            tint: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face { self.ior } else { 1.0 / self.ior }
+    }
+
+    // Fraction of light passing into and back out of the coat, for local directions wo and wi
+    fn layer_transmittance(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> Color {
+        let (cos_o, cos_i) = (wo.z().abs(), wi.z().abs());
+        let transmitted = (1.0 - fr_dielectric(cos_o, eta)) * (1.0 - fr_dielectric(cos_i, eta));
+
+        // Path length through the coat grows with the refracted angle of each pass
+        let refracted_cos = |c: f64| (1.0 - (1.0 - c * c) / (eta * eta)).max(1e-4).sqrt();
+        let passes = 1.0 / refracted_cos(cos_o) + 1.0 / refracted_cos(cos_i);
+        let tint = Color::new(self.tint.x().powf(passes), self.tint.y().powf(passes), self.tint.z().powf(passes));
+        transmitted * tint
+    }
+
+    // GGX reflection off the top of the coat: value times cos(theta_i), and pdf
+    fn coat_reflection(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> (f64, f64) {
+        if self.distribution.effectively_smooth() || !same_hemisphere(wo, wi) || wo.z() <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let wm = *wo + *wi;
+        if wm.near_zero() {
+            return (0.0, 0.0);
+        }
+        let wm = wm.unit_vector();
+        let d = &self.distribution;
+        let f = d.d(&wm) * d.g(wo, wi) * fr_dielectric(wo.dot(&wm).abs(), eta) / (4.0 * wo.z());
+        let pdf = d.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs());
+        (f, pdf)
+    }
+
+    // Probability of sampling the coat rather than the base
+    fn coat_probability(&self, wo: &Vec3, eta: f64) -> f64 {
+        fr_dielectric(wo.z().abs(), eta)
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        match self.sample(r_in, rec, sampler) {
+            Some(srec) => {
+                *attenuation = srec.attenuation;
+                *scattered = srec.scattered;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let eta = self.eta(rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let p_coat = self.coat_probability(&wo, eta);
+
+        let scattered = if sampler.next_f64() < p_coat {
+            if self.distribution.effectively_smooth() {
+                // Mirror reflection, with the Fresnel weight cancelled by the selection probability
+                let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+                return Some(ScatterRecord {
+                    attenuation: Color::new(1.0, 1.0, 1.0),
+                    scattered: Ray::new(rec.p, frame.transform(&wi)),
+                    pdf: 0.0,
+                });
+            }
+            let wm = self.distribution.sample_wm(&wo, sampler.next_f64(), sampler.next_f64());
+            let wi = Vec3::reflect(&-wo, &wm);
+            if !same_hemisphere(&wo, &wi) {
+                return None;
+            }
+            Ray::new(rec.p, frame.transform(&wi))
+        } else {
+            let srec = self.base.sample(r_in, rec, sampler)?;
+            if srec.pdf == 0.0 {
+                let wi = frame.local(&srec.scattered.direction().unit_vector());
+                let attenuation = srec.attenuation * self.layer_transmittance(&wo, &wi, eta) / (1.0 - p_coat);
+                return Some(ScatterRecord { attenuation, ..srec });
+            }
+            srec.scattered
+        };
+
+        let wi = scattered.direction();
+        let pdf = self.pdf(r_in, rec, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord { attenuation: self.eval(r_in, rec, &wi) / pdf, scattered, pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let eta = self.eta(rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        let wi_local = frame.local(&wi.unit_vector());
+        let (coat, _) = self.coat_reflection(&wo, &wi_local, eta);
+        Color::new(coat, coat, coat) + self.layer_transmittance(&wo, &wi_local, eta) * self.base.eval(r_in, rec, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let eta = self.eta(rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        let (_, coat_pdf) = self.coat_reflection(&wo, &frame.local(&wi.unit_vector()), eta);
+        let p_coat = self.coat_probability(&wo, eta);
+        p_coat * coat_pdf + (1.0 - p_coat) * self.base.pdf(r_in, rec, wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        // Emission from the base loses what the coat reflects back down
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        (1.0 - fr_dielectric(wo.z().abs(), self.eta(rec))) * self.base.emitted(r_in, rec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
@@ -0,0 +1,318 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ies::IesProfile;
+use crate::light_bvh::LightBounds;
+use crate::onb::Onb;
+use crate::rtweekend::*;
+use std::sync::{Arc, Weak};
+
+// A direction sampled towards a light from a shading point.
+pub struct LightSample {
+    pub wi: Vec3,
+    pub li: Color,
+    // Density with respect to solid angle; 1 for delta lights
+    pub pdf: f64,
+    // Distance to the sampled point on the light, INFINITY for lights at infinity
+    pub distance: f64,
+    pub is_delta: bool,
+}
+
+pub trait Light: Send + Sync {
+    // Samples incident radiance arriving at the shading point of `rec`.
+    fn sample_li(&self, rec: &HitRecord, sampler: &mut Sampler) -> Option<LightSample>;
+
+    // Solid angle density sample_li would have produced for direction `wi`.
+    fn pdf_li(&self, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Radiance carried by a ray that escapes the scene, for lights at infinity.
+    fn le(&self, _r: &Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Radiance leaving the surface point of `rec` in direction `w`, for area lights.
+    fn l(&self, _rec: &HitRecord, _w: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Bounds used by the light BVH; None for lights at infinity.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+// Power heuristic with beta = 2 for combining two sampling strategies.
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g == 0.0 {
+        0.0
+    } else {
+        f / (f + g)
+    }
+}
+
+// Photometric profile oriented in the world, with the basis' w axis as the profile's nadir.
+struct OrientedProfile {
+    profile: Arc<IesProfile>,
+    frame: Onb,
+}
+
+impl OrientedProfile {
+    // Relative intensity emitted along the world direction `w`.
+    fn scale(&self, w: &Vec3) -> f64 {
+        let local = self.frame.local(&w.unit_vector());
+        let gamma = local.z().clamp(-1.0, 1.0).acos().to_degrees();
+        let c = local.y().atan2(local.x()).to_degrees();
+        self.profile.relative(gamma, c)
+    }
+}
+
+// Point light with radiant intensity `intensity`, isotropic unless given an IES profile.
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+    profile: Option<OrientedProfile>,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self { position, intensity, profile: None }
+    }
+
+    // Shapes the emission with an IES profile whose nadir points along `nadir`. `intensity`
+    // then gives the intensity in the profile's brightest direction.
+    pub fn with_ies_profile(mut self, profile: Arc<IesProfile>, nadir: Vec3) -> Self {
+        self.profile = Some(OrientedProfile { profile, frame: Onb::new(&nadir) });
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, rec: &HitRecord, _sampler: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - rec.p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let wi = to_light / distance;
+        let scale = self.profile.as_ref().map_or(1.0, |p| p.scale(&-wi));
+        if scale <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: scale * self.intensity / distance_squared,
+            pdf: 1.0,
+            distance,
+            is_delta: true,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let phi = 4.0 * PI * self.intensity.max_component();
+        let bounds = Aabb::from_points(self.position, self.position);
+        Some(LightBounds::new(bounds, Vec3::new(0.0, 1.0, 0.0), phi, -1.0, 0.0, false))
+    }
+}
+
+// Point light restricted to a cone, fading smoothly between `falloff_start` and `total_width`
+// (half-angles in degrees, measured from `direction`).
+pub struct SpotLight {
+    pub position: Point3,
+    pub intensity: Color,
+    direction: Vec3,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+    profile: Option<OrientedProfile>,
+}
+
+impl SpotLight {
+    pub fn new(position: Point3, direction: Vec3, intensity: Color, total_width: f64, falloff_start: f64) -> Self {
+        let falloff_start = falloff_start.min(total_width);
+        Self {
+            position,
+            intensity,
+            direction: direction.unit_vector(),
+            cos_total_width: degrees_to_radians(total_width).cos(),
+            cos_falloff_start: degrees_to_radians(falloff_start).cos(),
+            profile: None,
+        }
+    }
+
+    // Shapes the emission inside the cone with an IES profile, its nadir along the spot direction.
+    pub fn with_ies_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(OrientedProfile { profile, frame: Onb::new(&self.direction) });
+        self
+    }
+
+    fn falloff(&self, w: &Vec3) -> f64 {
+        let cos_theta = w.dot(&self.direction);
+        if cos_theta < self.cos_total_width {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let t = (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, rec: &HitRecord, _sampler: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - rec.p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let wi = to_light / distance;
+        let mut falloff = self.falloff(&-wi);
+        if let Some(profile) = &self.profile {
+            falloff *= profile.scale(&-wi);
+        }
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: falloff * self.intensity / distance_squared,
+            pdf: 1.0,
+            distance,
+            is_delta: true,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Like the point light, phi is deliberately not reduced by the cone's solid angle;
+        // the orientation bounds already account for it
+        let phi = 4.0 * PI * self.intensity.max_component();
+        let cos_theta_e = (self.cos_total_width.acos() - self.cos_falloff_start.acos()).cos();
+        let bounds = Aabb::from_points(self.position, self.position);
+        Some(LightBounds::new(bounds, self.direction, phi, self.cos_falloff_start, cos_theta_e, false))
+    }
+}
+
+// Distant light arriving from a single direction with irradiance `irradiance`, like the sun.
+pub struct DirectionalLight {
+    pub irradiance: Color,
+    // Direction the light travels in
+    direction: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self { irradiance, direction: direction.unit_vector() }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            li: self.irradiance,
+            pdf: 1.0,
+            distance: INFINITY,
+            is_delta: true,
+        })
+    }
+}
+
+// Diffuse emitter with the shape of any Hittable. Add it both to the world, so rays can hit it,
+// and to the camera's lights, so it gets sampled directly.
+//
+// Emission leaves the front of the shape only, the side its outward normal points to, unless
+// the light is made two-sided.
+pub struct DiffuseAreaLight {
+    pub emit: Color,
+    shape: Arc<dyn Hittable>,
+    two_sided: bool,
+    me: Weak<DiffuseAreaLight>,
+}
+
+impl DiffuseAreaLight {
+    pub fn new(shape: Arc<dyn Hittable>, emit: Color) -> Arc<Self> {
+        Self::with_sides(shape, emit, false)
+    }
+
+    // Light emitting from both the front and the back of its shape
+    pub fn two_sided(shape: Arc<dyn Hittable>, emit: Color) -> Arc<Self> {
+        Self::with_sides(shape, emit, true)
+    }
+
+    fn with_sides(shape: Arc<dyn Hittable>, emit: Color, two_sided: bool) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self { emit, shape, two_sided, me: me.clone() })
+    }
+}
+
+impl Light for DiffuseAreaLight {
+    fn sample_li(&self, rec: &HitRecord, sampler: &mut Sampler) -> Option<LightSample> {
+        let direction = self.shape.random(&rec.p, sampler);
+        if direction.near_zero() {
+            return None;
+        }
+
+        let mut light_rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        if !self.shape.hit(&Ray::new(rec.p, direction), Interval::new(0.001, INFINITY), &mut light_rec, sampler) {
+            return None;
+        }
+        if !self.two_sided && !light_rec.front_face {
+            return None;
+        }
+        let pdf = self.shape.pdf_value(&rec.p, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            wi: direction.unit_vector(),
+            li: self.emit,
+            pdf,
+            distance: light_rec.t * direction.length(),
+            is_delta: false,
+        })
+    }
+
+    fn pdf_li(&self, rec: &HitRecord, wi: &Vec3) -> f64 {
+        self.shape.pdf_value(&rec.p, wi)
+    }
+
+    fn l(&self, rec: &HitRecord, _w: &Vec3) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.emit
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        let phi = sides * PI * self.emit.max_component() * self.shape.surface_area();
+        let (w, cos_theta_o) = self.shape.normal_cone();
+        Some(LightBounds::new(self.shape.bounding_box(), w, phi, cos_theta_o, 0.0, self.two_sided))
+    }
+}
+
+impl Hittable for DiffuseAreaLight {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !self.shape.hit(r, ray_t, rec, sampler) {
+            return false;
+        }
+        rec.light = self.me.upgrade().map(|light| light as Arc<dyn Light>);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.shape.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.shape.random(origin, sampler)
+    }
+}
@@ -0,0 +1,390 @@
+use crate::aabb::Aabb;
+use crate::hittable::HitRecord;
+use crate::light::Light;
+use crate::rtweekend::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON;
+
+// Conservative bounds on where a set of lights is, which way it emits and how much power it
+// carries. `w` and `cos_theta_o` bound the emitter normals; `cos_theta_e` bounds how far past
+// those normals the emission extends.
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    pub w: Vec3,
+    pub phi: f64,
+    pub cos_theta_o: f64,
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+// cos(max(0, a - b)) given the sines and cosines of a and b
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.0
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+// sin(max(0, a - b)) given the sines and cosines of a and b
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.0
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+// Rotates `v` by `theta` radians around the unit `axis` (Rodrigues' formula)
+fn rotate(v: Vec3, axis: Vec3, theta: f64) -> Vec3 {
+    let (sin, cos) = theta.sin_cos();
+    v * cos + axis.cross(&v) * sin + axis * (axis.dot(&v) * (1.0 - cos))
+}
+
+// Smallest cone (axis, cos half-angle) containing both cones
+fn union_cones(wa: Vec3, cos_a: f64, wb: Vec3, cos_b: f64) -> (Vec3, f64) {
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = wa.dot(&wb).clamp(-1.0, 1.0).acos();
+
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return (wa, cos_a);
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return (wb, cos_b);
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return (wa, -1.0);
+    }
+
+    let wr = wa.cross(&wb);
+    if wr.length_squared() == 0.0 {
+        return (wa, -1.0);
+    }
+    (rotate(wa, wr.unit_vector(), theta_o - theta_a), theta_o.cos())
+}
+
+impl LightBounds {
+    pub fn new(bounds: Aabb, w: Vec3, phi: f64, cos_theta_o: f64, cos_theta_e: f64, two_sided: bool) -> Self {
+        Self { bounds, w: w.unit_vector(), phi, cos_theta_o, cos_theta_e, two_sided }
+    }
+
+    pub fn union(a: &LightBounds, b: &LightBounds) -> LightBounds {
+        if a.phi == 0.0 {
+            return *b;
+        }
+        if b.phi == 0.0 {
+            return *a;
+        }
+        let (w, cos_theta_o) = union_cones(a.w, a.cos_theta_o, b.w, b.cos_theta_o);
+        LightBounds {
+            bounds: Aabb::surrounding(&a.bounds, &b.bounds),
+            w,
+            phi: a.phi + b.phi,
+            cos_theta_o,
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+
+    // Estimated contribution of the bounded lights at point `p` with surface normal `n`
+    pub fn importance(&self, p: Point3, n: Vec3) -> f64 {
+        // Distance to the center, clamped so points inside the bounds are not over-weighted
+        let pc = self.bounds.centroid();
+        let diagonal = self.bounds.diagonal().length();
+        let d2 = (p - pc).length_squared().max(diagonal / 2.0);
+        if d2 == 0.0 {
+            return self.phi;
+        }
+
+        let wi = (p - pc).unit_vector();
+        let mut cos_theta_w = self.w.dot(&wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+
+        // Half-angle of the cone of directions the bounds subtend from p
+        let radius_squared = (diagonal / 2.0) * (diagonal / 2.0);
+        let dist_squared = (p - pc).length_squared();
+        let (sin_theta_b, cos_theta_b) = if dist_squared < radius_squared {
+            (0.0, -1.0)
+        } else {
+            let sin2 = radius_squared / dist_squared;
+            (sin2.sqrt(), safe_sqrt(1.0 - sin2))
+        };
+
+        // Angle between wi and the emission cone, shrunk by the bounds' angular size
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.phi * cos_theta_p / d2;
+
+        // Account for the cosine at the receiving surface
+        if !n.near_zero() {
+            let cos_theta_i = wi.dot(&n.unit_vector()).abs();
+            let sin_theta_i = safe_sqrt(1.0 - cos_theta_i * cos_theta_i);
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+
+        importance.max(0.0)
+    }
+
+    // Orientation-aware surface area heuristic cost used while building the tree
+    fn cost(&self, centroid_bounds_axis: usize) -> f64 {
+        let theta_o = self.cos_theta_o.clamp(-1.0, 1.0).acos();
+        let theta_e = self.cos_theta_e.clamp(-1.0, 1.0).acos();
+        let theta_w = (theta_o + theta_e).min(PI);
+        let sin_theta_o = theta_o.sin();
+        let m_omega = 2.0 * PI * (1.0 - self.cos_theta_o)
+            + PI / 2.0 * (2.0 * theta_w * sin_theta_o - (theta_o - 2.0 * theta_w).cos() - 2.0 * theta_o * sin_theta_o + self.cos_theta_o);
+
+        let diagonal = self.bounds.diagonal();
+        let kr = if diagonal[centroid_bounds_axis] > 0.0 {
+            diagonal.max_component() / diagonal[centroid_bounds_axis]
+        } else {
+            1.0
+        };
+        self.phi * m_omega * kr * self.bounds.surface_area()
+    }
+}
+
+struct LightBvhNode {
+    bounds: LightBounds,
+    // Index of the light for leaves, of the second child for interior nodes
+    // (the first child always directly follows its parent)
+    child_or_light: usize,
+    // Index of the parent node, which the root has none of
+    parent: usize,
+    is_leaf: bool,
+}
+
+// Light sampler that picks among bounded lights by walking a bounding volume hierarchy built
+// over their LightBounds, choosing each child in proportion to its importance at the shading
+// point. Lights at infinity cannot be bounded and are sampled uniformly alongside the tree.
+pub struct LightBvh {
+    lights: Vec<Arc<dyn Light>>,
+    infinite_lights: Vec<Arc<dyn Light>>,
+    nodes: Vec<LightBvhNode>,
+    // Leaf node of each bounded light, which pmf() walks up from
+    leaves: HashMap<usize, usize>,
+}
+
+fn light_key(light: &Arc<dyn Light>) -> usize {
+    Arc::as_ptr(light) as *const u8 as usize
+}
+
+impl LightBvh {
+    pub fn new(all_lights: &[Arc<dyn Light>]) -> Self {
+        let mut bvh = LightBvh {
+            lights: Vec::new(),
+            infinite_lights: Vec::new(),
+            nodes: Vec::new(),
+            leaves: HashMap::new(),
+        };
+
+        let mut bounded = Vec::new();
+        for light in all_lights {
+            match light.bounds() {
+                Some(lb) => {
+                    if lb.phi > 0.0 {
+                        bounded.push((bvh.lights.len(), lb));
+                        bvh.lights.push(light.clone());
+                    }
+                }
+                None => bvh.infinite_lights.push(light.clone()),
+            }
+        }
+
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded, 0);
+        }
+        bvh
+    }
+
+    fn build(&mut self, items: &mut [(usize, LightBounds)], parent: usize) -> usize {
+        if items.len() == 1 {
+            let (light_index, lb) = items[0];
+            self.nodes.push(LightBvhNode { bounds: lb, child_or_light: light_index, parent, is_leaf: true });
+            self.leaves.insert(light_key(&self.lights[light_index]), self.nodes.len() - 1);
+            return self.nodes.len() - 1;
+        }
+
+        let mut bounds = Aabb::EMPTY;
+        let mut centroid_bounds = Aabb::EMPTY;
+        for (_, lb) in items.iter() {
+            bounds = Aabb::surrounding(&bounds, &lb.bounds);
+            let c = lb.bounds.centroid();
+            centroid_bounds = Aabb::surrounding(&centroid_bounds, &Aabb::from_points(c, c));
+        }
+
+        let mid = self.find_split(items, &bounds, &centroid_bounds);
+
+        let node_index = self.nodes.len();
+        self.nodes.push(LightBvhNode { bounds: items[0].1, child_or_light: 0, parent, is_leaf: false });
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build(left, node_index);
+        let second = self.build(right, node_index);
+
+        let node_bounds = LightBounds::union(&self.nodes[node_index + 1].bounds, &self.nodes[second].bounds);
+        self.nodes[node_index].bounds = node_bounds;
+        self.nodes[node_index].child_or_light = second;
+        node_index
+    }
+
+    // Partitions `items` with a bucketed cost heuristic and returns the split position.
+    fn find_split(&self, items: &mut [(usize, LightBounds)], bounds: &Aabb, centroid_bounds: &Aabb) -> usize {
+        const BUCKETS: usize = 12;
+        let mut best: Option<(f64, usize, usize)> = None;
+
+        for axis in 0..3 {
+            let extent = centroid_bounds.axis_interval(axis);
+            if extent.size() <= 0.0 {
+                continue;
+            }
+
+            let bucket_of = |lb: &LightBounds| -> usize {
+                let c = lb.bounds.centroid()[axis];
+                (((c - extent.min) / extent.size() * BUCKETS as f64) as usize).min(BUCKETS - 1)
+            };
+
+            let mut buckets: Vec<Option<LightBounds>> = vec![None; BUCKETS];
+            for (_, lb) in items.iter() {
+                let b = bucket_of(lb);
+                buckets[b] = Some(match buckets[b] {
+                    Some(existing) => LightBounds::union(&existing, lb),
+                    None => *lb,
+                });
+            }
+
+            for split in 0..BUCKETS - 1 {
+                let merge = |range: &[Option<LightBounds>]| -> Option<LightBounds> {
+                    range.iter().flatten().fold(None, |acc: Option<LightBounds>, lb| match acc {
+                        Some(a) => Some(LightBounds::union(&a, lb)),
+                        None => Some(*lb),
+                    })
+                };
+                let (below, above) = (merge(&buckets[..=split]), merge(&buckets[split + 1..]));
+                let cost = below.map_or(0.0, |b| b.cost(axis)) + above.map_or(0.0, |a| a.cost(axis));
+                if below.is_some() && above.is_some() && best.is_none_or(|(c, _, _)| cost < c) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        match best {
+            Some((_, axis, split)) => {
+                let extent = centroid_bounds.axis_interval(axis);
+                items.sort_by(|a, b| a.1.bounds.centroid()[axis].total_cmp(&b.1.bounds.centroid()[axis]));
+                let threshold = extent.min + extent.size() * (split + 1) as f64 / BUCKETS as f64;
+                let mid = items.partition_point(|(_, lb)| lb.bounds.centroid()[axis] < threshold);
+                mid.clamp(1, items.len() - 1)
+            }
+            None => {
+                // All centroids coincide; split in the middle along the longest axis
+                let axis = bounds.longest_axis();
+                items.sort_by(|a, b| a.1.bounds.centroid()[axis].total_cmp(&b.1.bounds.centroid()[axis]));
+                items.len() / 2
+            }
+        }
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let tree = usize::from(!self.nodes.is_empty());
+        if self.infinite_lights.is_empty() {
+            0.0
+        } else {
+            self.infinite_lights.len() as f64 / (self.infinite_lights.len() + tree) as f64
+        }
+    }
+
+    // Picks a light for the shading point in `rec`, returning it with its probability.
+    pub fn sample(&self, rec: &HitRecord, u: f64) -> Option<(Arc<dyn Light>, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let n = self.infinite_lights.len();
+            let index = ((u / p_infinite * n as f64) as usize).min(n - 1);
+            return Some((self.infinite_lights[index].clone(), p_infinite / n as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(ONE_MINUS_EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.is_leaf {
+                if node_index > 0 || node.bounds.importance(rec.p, rec.normal) > 0.0 {
+                    return Some((self.lights[node.child_or_light].clone(), pmf));
+                }
+                return None;
+            }
+
+            let c0 = self.nodes[node_index + 1].bounds.importance(rec.p, rec.normal);
+            let c1 = self.nodes[node.child_or_light].bounds.importance(rec.p, rec.normal);
+            if c0 == 0.0 && c1 == 0.0 {
+                return None;
+            }
+            let p0 = c0 / (c0 + c1);
+            if u < p0 {
+                node_index += 1;
+                u = (u / p0).min(ONE_MINUS_EPSILON);
+                pmf *= p0;
+            } else {
+                node_index = node.child_or_light;
+                u = ((u - p0) / (1.0 - p0)).min(ONE_MINUS_EPSILON);
+                pmf *= 1.0 - p0;
+            }
+        }
+    }
+
+    // Probability that sample() returns `light` for the shading point in `rec`, found by
+    // walking from the light's leaf up to the root.
+    pub fn pmf(&self, rec: &HitRecord, light: &Arc<dyn Light>) -> f64 {
+        let key = light_key(light);
+        let mut node_index = match self.leaves.get(&key) {
+            Some(leaf) => *leaf,
+            None => {
+                if self.infinite_lights.iter().any(|l| light_key(l) == key) {
+                    return self.infinite_probability() / self.infinite_lights.len() as f64;
+                }
+                return 0.0;
+            }
+        };
+
+        // A lone light is only picked where it can contribute, as in sample()
+        if node_index == 0 && self.nodes[0].bounds.importance(rec.p, rec.normal) <= 0.0 {
+            return 0.0;
+        }
+
+        let mut pmf = 1.0 - self.infinite_probability();
+        while node_index > 0 {
+            let parent = self.nodes[node_index].parent;
+            let c0 = self.nodes[parent + 1].bounds.importance(rec.p, rec.normal);
+            let c1 = self.nodes[self.nodes[parent].child_or_light].bounds.importance(rec.p, rec.normal);
+            if c0 + c1 == 0.0 {
+                return 0.0;
+            }
+            pmf *= if node_index == parent + 1 { c0 } else { c1 } / (c0 + c1);
+            node_index = parent;
+        }
+        pmf
+    }
+}
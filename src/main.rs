@@ -1,103 +1,134 @@
 mod rtweekend;
+mod sampler;
 mod vec3;
 mod ray;
 mod color;
 mod hittable;
 mod hittable_list;
+mod bvh;
 mod sphere;
 mod interval;
 mod camera;
 mod material;
+mod hdr_image;
+mod png;
+mod distribution;
+mod light;
+mod environment;
+mod onb;
+mod sky;
+mod aabb;
+mod quad;
+mod light_bvh;
+mod ies;
+mod fresnel;
+mod microfacet;
+mod spectrum;
+mod texture;
+mod perlin;
+mod principled;
+mod constant_medium;
+mod subsurface;
+mod layered;
+mod bump;
+mod alpha_mask;
+mod faces;
+mod transform;
+mod instance;
+mod triangle;
+mod ply;
+mod scene;
+mod pbrt;
+mod json;
+mod gltf;
+mod image_output;
+mod cli;
+mod presets;
 
-use rtweekend::{Point3, Vec3, PI, random_double, random_double_range};
-use hittable_list::HittableList;
-use sphere::Sphere;
-use camera::Camera;
-use material::{Lambertian, Metal, Dielectric};
-use color::Color;
-use std::rc::Rc;
+use rtweekend::Vec3;
+use sampler::Sampler;
+use scene::Scene;
+use cli::{Options, USAGE};
+use image_output::write_image;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
 fn main() {
-    // World
-    let mut world = HittableList::new(Vec::new());
-
-    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Some(ground_material.clone()))));
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_double();
-            let center = Point3::new(a as f64 + 0.9 * random_double(), 0.2, b as f64 + 0.9 * random_double());
+    if options.list_presets {
+        for (name, description) in presets::PRESETS {
+            println!("{:20} {}", name, description);
+        }
+        return;
+    }
 
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    let sphere_material = Rc::new(Lambertian::new(albedo));
-                    world.add(Rc::new(Sphere::new(center, 0.2, Some(sphere_material.clone()))));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = random_double_range(0.0, 0.5);
-                    let sphere_material = Rc::new(Metal::new(albedo, fuzz));
-                    world.add(Rc::new(Sphere::new(center, 0.2, Some(sphere_material.clone()))));
-                } else {
-                    // glass
-                    let sphere_material = Rc::new(Dielectric::new(1.5));
-                    world.add(Rc::new(Sphere::new(center, 0.2, Some(sphere_material.clone()))));
+    // A scene file given on the command line replaces the built-in scenes
+    let mut scene = match &options.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                let separator = if e.line == 0 { " " } else { "" };
+                eprintln!("{}:{}{}", path.display(), separator, e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let name = options.preset.as_deref().unwrap_or(presets::DEFAULT_PRESET);
+            // Presets made with random numbers come out the same for the same seed too
+            let mut sampler = options.seed.map_or_else(Sampler::from_entropy, Sampler::new);
+            match presets::build(name, &mut sampler) {
+                Some(scene) => scene,
+                None => {
+                    eprintln!("error: no preset named '{}'; --list-presets shows them all", name);
+                    std::process::exit(2);
                 }
             }
         }
-    }
-
-    let material1 = Rc::new(Dielectric::new(1.5));
-    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Some(material1.clone()))));
-
-    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Rc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Some(material2.clone()))));
+    };
+    options.apply(&mut scene.camera);
 
-    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Rc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Some(material3.clone()))));
-
-
-    let mut camera = Camera::new();
-    camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = 1200;
-    camera.samples_per_pixel = 500;
-    camera.max_depth = 50;
-    camera.vfov = 20.0;
-    camera.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    camera.lookat = Point3::new(0.0, 0.0, 0.0);
-    camera.vup = Vec3::new(0.0, 1.0, 0.0);
-    camera.defocus_angle = 0.6;
-    camera.focus_dist = 10.0;
-    
-
-
-    // let material_ground = Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    // let material_center = Rc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
-    // let material_left = Rc::new(Dielectric::new(1.5));
-    // let material_bubble = Rc::new(Dielectric::new(1.00 / 1.50));
-    // let material_right = Rc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
+    // One path traced through the pixel, printed bounce by bounce instead of an image
+    if let Some((i, j)) = options.debug_pixel {
+        let (color, path) = scene.camera.debug_path(i, j, &scene.world);
+        let show = |v: Vec3| format!("({:.4}, {:.4}, {:.4})", v.x(), v.y(), v.z());
+        for vertex in &path {
+            let side = if vertex.front_face { "front" } else { "back" };
+            println!(
+                "bounce {}: t {:.4}, point {}, normal {}, {} face, throughput {}",
+                vertex.depth, vertex.t, show(vertex.p), show(vertex.normal), side, show(vertex.throughput)
+            );
+        }
+        println!("color {}", show(color));
+        return;
+    }
 
-    // world.add(Rc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Some(material_ground.clone()))));
-    // world.add(Rc::new(Sphere::new(Point3::new(0.0, 0.0, -1.2), 0.5, Some(material_center.clone()))));
-    // world.add(Rc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, Some(material_left.clone()))));
-    // world.add(Rc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.4, Some(material_bubble.clone()))));
-    // world.add(Rc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, Some(material_right.clone()))));
+    let image = scene.camera.render_image(&scene.world);
 
-    // // Camera
-    // let mut camera = Camera::new();
-    // camera.aspect_ratio = 16.0 / 9.0;
-    // camera.image_width = 400;
-    // camera.samples_per_pixel = 100;
-    // camera.max_depth = 50;
-    // camera.vfov = 20.0;
-    // camera.lookfrom = Point3::new(-2.0, 2.0, 1.0);
-    // camera.lookat = Point3::new(0.0, 0.0, -1.0);
-    // camera.vup = Vec3::new(0.0, 1.0, 0.0);
-    // camera.defocus_angle = 10.0;
-    // camera.focus_dist = 3.4;
-    
-    camera.render(&world);
+    let format = options.output_format();
+    let written = match &options.output {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_image(&mut out, &image, format)?;
+            out.flush()
+        }),
+        None => write_image(&mut std::io::stdout().lock(), &image, format),
+    };
+    if let Err(e) = written {
+        match &options.output {
+            Some(path) => eprintln!("{}: {}", path.display(), e),
+            None => eprintln!("error writing image: {}", e),
+        }
+        std::process::exit(1);
+    }
 }
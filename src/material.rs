@@ -1,99 +1,666 @@
 use crate::rtweekend::*;
 use crate::hittable::*;
 use crate::ray::*;
-use crate::color::*;
+use crate::fresnel::{fr_complex_color, fr_dielectric, schlick_color, Complex, ThinFilm};
+use crate::microfacet::{cos_theta, roughness_to_alpha, same_hemisphere, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::spectrum::{reflectance_to_rgb, unbounded_rgb_to_spectrum};
+use crate::texture::{SolidColor, Texture};
+use std::sync::Arc;
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool;
+// Result of Material::sample: the scattered ray, its weight f * cos / pdf, and the solid angle
+// density it was sampled with. A zero pdf marks a specular direction that light sampling could
+// not have produced.
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub pdf: f64,
+}
+
+pub trait Material: Send + Sync {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool;
+
+    // scatter() together with the density of the direction it picked. Materials that can pick
+    // either a specular or a non-specular lobe override this, since pdf() alone cannot tell
+    // which one was taken.
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        if !self.scatter(r_in, rec, &mut attenuation, &mut scattered, sampler) {
+            return None;
+        }
+        let pdf = self.pdf(r_in, rec, &scattered.direction());
+        Some(ScatterRecord { attenuation, scattered, pdf })
+    }
+
+    // BSDF times the cosine term for light arriving from direction `wi`, used for light sampling.
+    // Purely specular materials keep the default of black.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density with which scatter() picks `wi`; zero for specular materials.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Radiance emitted by the surface itself towards the incoming ray. Emission found this way is
+    // only picked up by paths that hit it; wrap the shape in a DiffuseAreaLight to sample it.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // True if scattering depends on the ray's wavelength, so a spectral path through it is only
+    // valid for its hero wavelength.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone)]
 pub struct Lambertian {
-    albedo: Color,
+    // No texture means the albedo comes from the vertex colors of the mesh that was hit
+    tex: Option<Arc<dyn Texture>>,
 }
 
 impl Lambertian {
     pub fn new(a: Color) -> Self {
-        Self { albedo: a }
+        Self::from_texture(Arc::new(SolidColor::new(a)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex: Some(tex) }
+    }
+
+    // Colored by the vertex colors of meshes, and white on anything without them
+    pub fn from_vertex_colors() -> Self {
+        Self { tex: None }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        match &self.tex {
+            Some(tex) => tex.value(rec.u, rec.v, &rec.p),
+            None => rec.color.unwrap_or(Color::new(1.0, 1.0, 1.0)),
+        }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
         // eprintln!("Lambertian::scatter");
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
         *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
+        *attenuation = self.albedo(rec);
         true
     }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let cosine = rec.normal.dot(&wi.unit_vector());
+        if cosine <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.albedo(rec) * (cosine / PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let cosine = rec.normal.dot(&wi.unit_vector());
+        if cosine <= 0.0 { 0.0 } else { cosine / PI }
+    }
+}
+
+#[derive(Clone)]
+enum ConductorFresnel {
+    // Schlick's approximation using the albedo as the reflectance at normal incidence
+    Schlick(Color),
+    // Complex index of refraction eta + i k, per RGB channel
+    Complex { eta: Color, k: Color },
 }
 
+// Rough conductor with a GGX (Trowbridge-Reitz) microfacet distribution. Rays are reflected off
+// visible microfacet normals, so little energy is lost below the surface.
+//
+// An optional thin film on top (an oxide layer, or oil) adds interference colors.
 #[derive(Clone)]
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    fresnel: ConductorFresnel,
+    distribution: TrowbridgeReitz,
+    thin_film: Option<ThinFilm>,
+}
+
+fn gray(r: f64) -> Color {
+    Color::new(r, r, r)
+}
+
+fn average(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// Reflectance of `film` as a color: at the ray's wavelength in spectral rendering, or integrated
+// over the visible spectrum for RGB rays. `reflectance` gives the film's reflectance per
+// wavelength.
+//
+// Saturated interference colors can fall outside the sRGB gamut, so the RGB reflectance is
+// clamped to [0,1] per channel. Otherwise 1 - R, the transmitted part, could go negative.
+fn thin_film_color(r_in: &Ray, reflectance: impl Fn(f64) -> f64) -> Color {
+    if r_in.wavelength() > 0.0 {
+        gray(reflectance(r_in.wavelength()))
+    } else {
+        let c = reflectance_to_rgb(reflectance);
+        Color::new(c.x().clamp(0.0, 1.0), c.y().clamp(0.0, 1.0), c.z().clamp(0.0, 1.0))
+    }
 }
 
 impl Metal {
+    // Tinted metal reflecting albedo `a` head-on. The old fuzz parameter `f` is now the GGX
+    // roughness in [0,1].
     pub fn new(a: Color, f: f64) -> Self {
-        Self { albedo: a, fuzz: if f < 1.0 { f } else { 1.0 } }
+        Self::with_fresnel(ConductorFresnel::Schlick(a), f)
+    }
+
+    // Physically based conductor from its complex index of refraction
+    pub fn conductor(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::with_fresnel(ConductorFresnel::Complex { eta, k }, roughness)
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::conductor(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::conductor(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::conductor(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::conductor(Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147), roughness)
+    }
+
+    fn with_fresnel(fresnel: ConductorFresnel, roughness: f64) -> Self {
+        Self { fresnel, distribution: TrowbridgeReitz::isotropic(roughness_to_alpha(roughness)), thin_film: None }
+    }
+
+    // Coats the metal with a film `thickness` nanometers thick
+    pub fn with_thin_film(mut self, thickness: f64, ior: f64) -> Self {
+        self.thin_film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    // Complex IOR per channel. A Schlick albedo is matched by a non-absorbing IOR with the same
+    // reflectance at normal incidence.
+    fn complex_ior(&self) -> (Color, Color) {
+        match &self.fresnel {
+            ConductorFresnel::Schlick(r0) => {
+                let eta = |r0: f64| {
+                    let s = r0.clamp(0.0, 0.99).sqrt();
+                    (1.0 + s) / (1.0 - s)
+                };
+                (Color::new(eta(r0.x()), eta(r0.y()), eta(r0.z())), Color::new(0.0, 0.0, 0.0))
+            }
+            ConductorFresnel::Complex { eta, k } => (*eta, *k),
+        }
+    }
+
+    fn fresnel(&self, r_in: &Ray, cos_theta: f64) -> Color {
+        if let Some(film) = &self.thin_film {
+            let (eta, k) = self.complex_ior();
+            return thin_film_color(r_in, |lambda| {
+                let base = Complex::new(unbounded_rgb_to_spectrum(eta, lambda), unbounded_rgb_to_spectrum(k, lambda));
+                film.reflectance(cos_theta, 1.0, base, lambda)
+            });
+        }
+        match &self.fresnel {
+            ConductorFresnel::Schlick(r0) => schlick_color(cos_theta, *r0),
+            ConductorFresnel::Complex { eta, k } => fr_complex_color(cos_theta, *eta, *k),
+        }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        // eprintln!("Metal::scatter");
-        let mut reflected = Vec3::reflect(&r_in.direction(), &rec.normal);
-        reflected = reflected.unit_vector() + self.fuzz * Vec3::random_unit_vector();
-        
-        *scattered = Ray::new(rec.p, reflected);
-        *attenuation = self.albedo;
-        scattered.direction().dot(&rec.normal) > 0.0
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            *scattered = Ray::new(rec.p, frame.transform(&wi));
+            *attenuation = self.fresnel(r_in, wo.z());
+            return true;
+        }
+
+        let wm = self.distribution.sample_wm(&wo, sampler.next_f64(), sampler.next_f64());
+        let wi = Vec3::reflect(&-wo, &wm);
+        if !same_hemisphere(&wo, &wi) {
+            return false;
+        }
+
+        // f * cos(theta_i) / pdf simplifies to F * G / G1(wo) for visible normal sampling
+        *scattered = Ray::new(rec.p, frame.transform(&wi));
+        *attenuation = self.fresnel(r_in, wo.dot(&wm).abs()) * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if self.distribution.effectively_smooth() {
+            return black;
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        let wi = frame.local(&wi.unit_vector());
+        if !same_hemisphere(&wo, &wi) || wo.z() <= 0.0 {
+            return black;
+        }
+
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return black;
+        }
+        let wm = wm.unit_vector();
+        let f = self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z() * wi.z());
+        self.fresnel(r_in, wo.dot(&wm).abs()) * (f * wi.z())
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        let wi = frame.local(&wi.unit_vector());
+        if !same_hemisphere(&wo, &wi) || wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = wm.unit_vector();
+        self.distribution.pdf(&wo, &wm) / (4.0 * wo.dot(&wm).abs())
     }
-}
 
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
+}
 
+// Glass-like boundary between two media. With a non-zero roughness, reflection and transmission
+// are spread over a GGX microfacet distribution (frosted glass); otherwise it is a perfectly
+// smooth interface. `refraction_index` is relative to the medium on the outward-normal side.
+//
+// Light travelling through the interior is attenuated following the Beer-Lambert law, so
+// thick colored glass tints more than thin glass. This assumes a closed object: the distance
+// inside is the length of the segment ending at a back-face hit.
+//
+// With a dispersion model the index depends on the wavelength of the ray, which splits white
+// light into colors in spectral rendering. RGB rays carry no wavelength and use
+// `refraction_index`.
+//
+// A thin film on the outside of the surface gives soap bubble and oil slick colors. A soap
+// bubble is a film of soapy water around air: `Dielectric::new(1.0).with_thin_film(400.0, 1.33)`.
 #[derive(Clone)]
 pub struct Dielectric {
     refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    // Absorption coefficient per unit distance, per RGB channel
+    absorption: Color,
+    dispersion: Dispersion,
+    thin_film: Option<ThinFilm>,
+}
+
+// Index of refraction as a function of wavelength. Coefficients use wavelengths in micrometers.
+#[derive(Copy, Clone, Debug)]
+pub enum Dispersion {
+    None,
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum b_i lambda^2 / (lambda^2 - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn ior(&self, lambda_nm: f64) -> Option<f64> {
+        let l2 = (lambda_nm / 1000.0) * (lambda_nm / 1000.0);
+        match *self {
+            Dispersion::None => None,
+            Dispersion::Cauchy { a, b } => Some(a + b / l2),
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                Some(n2.max(1.0).sqrt())
+            }
+        }
+    }
 }
 
 impl Dielectric {
     pub fn new(ri: f64) -> Self {
-        Self { refraction_index: ri }
+        Self::rough(ri, 0.0)
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Use Schlick's approximation for reflectance
-        let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-        r0 = r0 * r0;
-        r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
+    pub fn rough(ri: f64, roughness: f64) -> Self {
+        Self {
+            refraction_index: ri,
+            distribution: TrowbridgeReitz::isotropic(roughness_to_alpha(roughness)),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            dispersion: Dispersion::None,
+            thin_film: None,
+        }
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        let ri = if rec.front_face {
-            1.0 / self.refraction_index
+    // Schott N-BK7 crown glass
+    pub fn bk7() -> Self {
+        Self::new(1.5168).with_sellmeier([1.03961212, 0.231792344, 1.01046945], [0.00600069867, 0.0200179144, 103.560653])
+    }
+
+    pub fn diamond() -> Self {
+        Self::new(2.4175).with_sellmeier([4.3356, 0.3306, 0.0], [0.011236, 0.030625, 0.0])
+    }
+
+    pub fn with_cauchy(mut self, a: f64, b: f64) -> Self {
+        self.dispersion = Dispersion::Cauchy { a, b };
+        self
+    }
+
+    pub fn with_sellmeier(mut self, b: [f64; 3], c: [f64; 3]) -> Self {
+        self.dispersion = Dispersion::Sellmeier { b, c };
+        self
+    }
+
+    // Coats the surface with a film `thickness` nanometers thick
+    pub fn with_thin_film(mut self, thickness: f64, ior: f64) -> Self {
+        self.thin_film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = Color::new(absorption.x().max(0.0), absorption.y().max(0.0), absorption.z().max(0.0));
+        self
+    }
+
+    // Absorption chosen so that light keeps `color` after travelling `distance` inside.
+    pub fn with_transmission_color(self, color: Color, distance: f64) -> Self {
+        let sigma = |c: f64| if c <= 0.0 { INFINITY } else { -c.min(1.0).ln() / distance };
+        self.with_absorption(Color::new(sigma(color.x()), sigma(color.y()), sigma(color.z())))
+    }
+
+    // Beer-Lambert transmittance of the segment that reached `rec`, if it ran inside the medium
+    fn transmittance(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let distance = rec.t * r_in.direction().length();
+        Color::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+
+    // Ratio of the IOR on the far side of the surface to the IOR on the side the ray arrives
+    // from. The normal always faces the incoming ray, so leaving the object inverts the ratio.
+    fn relative_eta(&self, r_in: &Ray, rec: &HitRecord) -> f64 {
+        let ri = if r_in.wavelength() > 0.0 {
+            self.dispersion.ior(r_in.wavelength()).unwrap_or(self.refraction_index)
         } else {
             self.refraction_index
         };
+        if rec.front_face { ri } else { 1.0 / ri }
+    }
+
+    // Reflectance of the interface for light arriving at `cos_theta` to the (micro)surface
+    // normal. Without a film this is the exact dielectric Fresnel term in every channel.
+    fn fresnel(&self, r_in: &Ray, rec: &HitRecord, cos_theta: f64) -> Color {
+        let eta = self.relative_eta(r_in, rec);
+        match &self.thin_film {
+            None => gray(fr_dielectric(cos_theta, eta)),
+            Some(film) => {
+                // The film is on the outside, so rays leaving the object meet it last
+                let (eta_i, eta_t) = if rec.front_face { (1.0, eta) } else { (1.0 / eta, 1.0) };
+                thin_film_color(r_in, |lambda| film.reflectance(cos_theta, eta_i, Complex::new(eta_t, 0.0), lambda))
+            }
+        }
+    }
+
+    // Refracts `wo` (pointing away from the surface, on the side of `n`) through a boundary with
+    // relative IOR `eta`, or returns None on total internal reflection.
+    fn refract(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+        let (mut n, mut eta) = (*n, eta);
+        let mut cos_theta_i = n.dot(wo);
+        if cos_theta_i < 0.0 {
+            eta = 1.0 / eta;
+            cos_theta_i = -cos_theta_i;
+            n = -n;
+        }
+
+        let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+        let sin2_theta_t = sin2_theta_i / (eta * eta);
+        if sin2_theta_t >= 1.0 {
+            return None;
+        }
+        let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+        Some(-*wo / eta + (cos_theta_i / eta - cos_theta_t) * n)
+    }
+
+    fn scatter_smooth(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        let eta = self.relative_eta(r_in, rec);
         let unit_direction = r_in.direction().unit_vector();
         let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = ri * sin_theta > 1.0;
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > random_double() {
-            Vec3::reflect(&unit_direction, &rec.normal)
+        // Total internal reflection shows up as a reflectance of one. A colored reflectance
+        // (thin film) picks a branch by its average and reweights.
+        let r = self.fresnel(r_in, rec, cos_theta);
+        let p = average(r);
+        let (direction, weight) = if p > sampler.next_f64() {
+            (Vec3::reflect(&unit_direction, &rec.normal), r / p)
         } else {
-            Vec3::refract(&unit_direction, &rec.normal, ri)
+            (Vec3::refract(&unit_direction, &rec.normal, 1.0 / eta), (Color::new(1.0, 1.0, 1.0) - r) / (1.0 - p))
         };
 
+        *attenuation = self.transmittance(r_in, rec) * weight;
+
         *scattered = Ray::new(rec.p, direction);
         true
-        
     }
-}
\ No newline at end of file
+
+    fn eval_pdf(&self, r_in: &Ray, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let fresnel = |cos_theta: f64| self.fresnel(r_in, rec, cos_theta);
+        Dielectric::microfacet_eval_pdf(&self.distribution, wo, wi, self.relative_eta(r_in, rec), &fresnel)
+    }
+
+    // Samples reflection or transmission off a visible microfacet, in proportion to its average
+    // Fresnel reflectance `fresnel(cos_theta)`. `wo` is in the local frame with wo.z > 0.
+    pub fn microfacet_sample(
+        distribution: &TrowbridgeReitz,
+        wo: &Vec3,
+        eta: f64,
+        fresnel: &dyn Fn(f64) -> Color,
+        sampler: &mut Sampler,
+    ) -> Option<Vec3> {
+        let wm = distribution.sample_wm(wo, sampler.next_f64(), sampler.next_f64());
+        let r = average(fresnel(wo.dot(&wm)));
+        if sampler.next_f64() < r {
+            let wi = Vec3::reflect(&-*wo, &wm);
+            if same_hemisphere(wo, &wi) { Some(wi) } else { None }
+        } else {
+            match Dielectric::refract(wo, &wm, eta) {
+                Some(wi) if !same_hemisphere(wo, &wi) && wi.z() != 0.0 => Some(wi),
+                _ => None,
+            }
+        }
+    }
+
+    // Microfacet BSDF value times |cos(theta_i)| and the sampling density, both in the local
+    // frame around the normal. Like the smooth case, transmission is not scaled by 1/eta^2,
+    // which cancels out for closed objects.
+    pub fn microfacet_eval_pdf(
+        distribution: &TrowbridgeReitz,
+        wo: &Vec3,
+        wi: &Vec3,
+        eta: f64,
+        fresnel: &dyn Fn(f64) -> Color,
+    ) -> (Color, f64) {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let (cos_o, cos_i) = (cos_theta(wo), cos_theta(wi));
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return (black, 0.0);
+        }
+        let reflect = cos_o * cos_i > 0.0;
+
+        // Generalized half vector
+        let etap = if reflect { 1.0 } else { eta };
+        let wm = *wi * etap + *wo;
+        if wm.near_zero() {
+            return (black, 0.0);
+        }
+        let mut wm = wm.unit_vector();
+        if wm.z() < 0.0 {
+            wm = -wm;
+        }
+
+        // Discard back-facing microfacets
+        if wm.dot(wi) * cos_i < 0.0 || wm.dot(wo) * cos_o < 0.0 {
+            return (black, 0.0);
+        }
+
+        let r = fresnel(wo.dot(&wm));
+        let t = Color::new(1.0, 1.0, 1.0) - r;
+        let d = distribution;
+        if reflect {
+            let f = d.d(&wm) * d.g(wo, wi) / (4.0 * cos_i * cos_o).abs();
+            let pdf = d.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * average(r);
+            (r * (f * cos_i.abs()), pdf)
+        } else {
+            let denom = wi.dot(&wm) + wo.dot(&wm) / eta;
+            let denom2 = denom * denom;
+            let f = d.d(&wm) * d.g(wo, wi) * (wi.dot(&wm) * wo.dot(&wm) / (cos_i * cos_o * denom2)).abs();
+            let dwm_dwi = wi.dot(&wm).abs() / denom2;
+            let pdf = d.pdf(wo, &wm) * dwm_dwi * average(t);
+            (t * (f * cos_i.abs()), pdf)
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        if self.distribution.effectively_smooth() {
+            return self.scatter_smooth(r_in, rec, attenuation, scattered, sampler);
+        }
+
+        let eta = self.relative_eta(r_in, rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let fresnel = |cos_theta: f64| self.fresnel(r_in, rec, cos_theta);
+        let wi = match Dielectric::microfacet_sample(&self.distribution, &wo, eta, &fresnel, sampler) {
+            Some(wi) => wi,
+            None => return false,
+        };
+
+        let (f, pdf) = self.eval_pdf(r_in, rec, &wo, &wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = self.transmittance(r_in, rec) * (f / pdf);
+        *scattered = Ray::new(rec.p, frame.transform(&wi));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        if self.distribution.effectively_smooth() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        let (f, _) = self.eval_pdf(r_in, rec, &wo, &frame.local(&wi.unit_vector()));
+        self.transmittance(r_in, rec) * f
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+        self.eval_pdf(r_in, rec, &wo, &frame.local(&wi.unit_vector())).1
+    }
+
+    fn is_dispersive(&self) -> bool {
+        !matches!(self.dispersion, Dispersion::None) || self.thin_film.is_some()
+    }
+}
+
+// Phase function scattering uniformly in all directions, used inside participating media.
+//
+// `extinction_ratio` is the medium's extinction per channel relative to the rate collisions are
+// sampled at. Below one, a collision is real with probability of the average ratio and a null
+// collision otherwise, which continues straight on.
+#[derive(Clone)]
+pub struct Isotropic {
+    albedo: Color,
+    extinction_ratio: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo, extinction_ratio: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn with_extinction_ratio(mut self, ratio: Color) -> Self {
+        self.extinction_ratio = ratio;
+        self
+    }
+
+    fn real_probability(&self) -> f64 {
+        average(self.extinction_ratio).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        match self.sample(r_in, rec, sampler) {
+            Some(srec) => {
+                *attenuation = srec.attenuation;
+                *scattered = srec.scattered;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // A null collision passes the ray on unchanged, which is a specular event
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let p_real = self.real_probability();
+        if p_real >= 1.0 || sampler.next_f64() < p_real {
+            Some(ScatterRecord {
+                attenuation: self.albedo * self.extinction_ratio / p_real,
+                scattered: Ray::new(rec.p, Vec3::random_unit_vector(sampler)),
+                pdf: p_real / (4.0 * PI),
+            })
+        } else {
+            Some(ScatterRecord {
+                attenuation: (Color::new(1.0, 1.0, 1.0) - self.extinction_ratio) / (1.0 - p_real),
+                scattered: Ray::new(rec.p, r_in.direction()),
+                pdf: 0.0,
+            })
+        }
+    }
+
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        self.albedo * self.extinction_ratio / (4.0 * PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        self.real_probability() / (4.0 * PI)
+    }
+}
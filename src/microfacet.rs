@@ -0,0 +1,149 @@
+use crate::rtweekend::*;
+
+// Trig helpers for directions in a local shading frame where the normal is +z.
+pub fn cos_theta(w: &Vec3) -> f64 {
+    w.z()
+}
+
+pub fn abs_cos_theta(w: &Vec3) -> f64 {
+    w.z().abs()
+}
+
+fn cos2_theta(w: &Vec3) -> f64 {
+    w.z() * w.z()
+}
+
+fn sin2_theta(w: &Vec3) -> f64 {
+    (1.0 - cos2_theta(w)).max(0.0)
+}
+
+fn tan2_theta(w: &Vec3) -> f64 {
+    sin2_theta(w) / cos2_theta(w)
+}
+
+fn cos_phi(w: &Vec3) -> f64 {
+    let sin_theta = sin2_theta(w).sqrt();
+    if sin_theta == 0.0 { 1.0 } else { (w.x() / sin_theta).clamp(-1.0, 1.0) }
+}
+
+fn sin_phi(w: &Vec3) -> f64 {
+    let sin_theta = sin2_theta(w).sqrt();
+    if sin_theta == 0.0 { 0.0 } else { (w.y() / sin_theta).clamp(-1.0, 1.0) }
+}
+
+pub fn same_hemisphere(a: &Vec3, b: &Vec3) -> bool {
+    a.z() * b.z() > 0.0
+}
+
+// Maps artist-facing roughness in [0,1] to the microfacet alpha parameter.
+pub fn roughness_to_alpha(roughness: f64) -> f64 {
+    let r = roughness.clamp(0.0, 1.0);
+    r * r
+}
+
+// Trowbridge-Reitz (GGX) microfacet distribution with Smith masking-shadowing and sampling of
+// the visible normals (Heitz 2018).
+#[derive(Copy, Clone, Debug)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        let mut alpha_x = alpha_x;
+        let mut alpha_y = alpha_y;
+        // Very small alphas are numerically unstable; those surfaces are treated as smooth anyway
+        if !(alpha_x < 1e-4 && alpha_y < 1e-4) {
+            alpha_x = alpha_x.max(1e-4);
+            alpha_y = alpha_y.max(1e-4);
+        }
+        Self { alpha_x, alpha_y }
+    }
+
+    pub fn isotropic(alpha: f64) -> Self {
+        Self::new(alpha, alpha)
+    }
+
+    // Below this roughness the surface is handled as a perfect specular reflector/refractor
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let tan2 = tan2_theta(wm);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let cos4 = cos2_theta(wm) * cos2_theta(wm);
+        if cos4 < 1e-16 {
+            return 0.0;
+        }
+        let (cp, sp) = (cos_phi(wm), sin_phi(wm));
+        let e = tan2 * (cp * cp / (self.alpha_x * self.alpha_x) + sp * sp / (self.alpha_y * self.alpha_y));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e) * (1.0 + e))
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let tan2 = tan2_theta(w);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let (cp, sp) = (cos_phi(w), sin_phi(w));
+        let alpha2 = cp * cp * self.alpha_x * self.alpha_x + sp * sp * self.alpha_y * self.alpha_y;
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Distribution of normals visible from direction w
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        let cos = abs_cos_theta(w);
+        if cos == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / cos * self.d(wm) * w.dot(wm).abs()
+    }
+
+    pub fn pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.d_visible(w, wm)
+    }
+
+    // Samples a microfacet normal visible from w, distributed according to d_visible.
+    pub fn sample_wm(&self, w: &Vec3, u0: f64, u1: f64) -> Vec3 {
+        // Transform w to the hemispherical configuration
+        let mut wh = Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        // Orthonormal basis for visible normal sampling
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // Uniformly distributed point on the unit disk, warped to the projection of the visible
+        // hemisphere
+        let r = u0.sqrt();
+        let phi = 2.0 * PI * u1;
+        let px = r * phi.cos();
+        let mut py = r * phi.sin();
+        let h = (1.0 - px * px).max(0.0).sqrt();
+        let t = (1.0 + wh.z()) / 2.0;
+        py = (1.0 - t) * h + t * py;
+
+        // Reproject onto the hemisphere and back to the ellipsoid configuration
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)).unit_vector()
+    }
+}
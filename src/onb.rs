@@ -0,0 +1,48 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis with `w` along the given direction.
+#[derive(Copy, Clone)]
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Self { axis: [u, v, w] }
+    }
+
+    // Basis with `w` along `n` and `u` along the part of `t` perpendicular to it, falling back
+    // to an arbitrary `u` when `t` is zero or parallel to `n`.
+    pub fn from_normal_tangent(n: &Vec3, t: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let t = *t - t.dot(&w) * w;
+        if t.length_squared() < 1e-12 {
+            return Self::new(n);
+        }
+        let u = t.unit_vector();
+        let v = w.cross(&u);
+        Self { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    // Transform from basis coordinates to world space
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.axis[0] + v.y() * self.axis[1] + v.z() * self.axis[2]
+    }
+
+    // Transform from world space to basis coordinates
+    pub fn local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.axis[0]), v.dot(&self.axis[1]), v.dot(&self.axis[2]))
+    }
+}
@@ -0,0 +1,880 @@
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::light::{DiffuseAreaLight, DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::ply;
+use crate::rtweekend::*;
+use crate::scene::{Scene, SceneError};
+use crate::sphere::Sphere;
+use crate::transform::Transform;
+use crate::triangle::{make_mesh, Triangle, TriangleMesh};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Importer for a subset of the pbrt-v4 scene format, so reference scenes written for pbrt can
+// be rendered here:
+//
+//     Camera "perspective" (fov, lensradius, focaldistance)
+//     Film (xresolution, yresolution), Sampler (pixelsamples), Integrator (maxdepth)
+//     Shape "sphere" (radius), "trianglemesh" (P, indices, N, uv), "plymesh" (filename)
+//     Material and MakeNamedMaterial "diffuse", "conductor", "dielectric", and NamedMaterial
+//     LightSource "point", "spot", "distant", and "infinite" with a constant color
+//     AreaLightSource "diffuse"
+//     AttributeBegin/End, ObjectBegin/End/ObjectInstance, ReverseOrientation, Include/Import
+//     Identity, Translate, Scale, Rotate, LookAt, Transform, ConcatTransform,
+//     CoordinateSystem and CoordSysTransform
+//
+// Anything else that changes the image, such as textures, media or other shape types, is
+// skipped with a warning. Spectra are only understood as RGB or as pbrt's named metal spectra.
+//
+// pbrt's world is left-handed and this renderer's is right-handed, so everything, camera
+// included, is mirrored in x on the way in; the image comes out the same way round.
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+fn error<T>(line: usize, column: usize, message: String) -> Result<T> {
+    Err(SceneError { line, column, message })
+}
+
+fn warn(line: usize, column: usize, message: &str) {
+    eprintln!("warning: {}:{}: {}", line, column, message);
+}
+
+// Transform from pbrt's left-handed world to this renderer's
+fn mirror() -> Transform {
+    Transform::scale(-1.0, 1.0, 1.0)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .or_else(|e| error(0, 0, format!("can't read scene file {}: {}", path.display(), e)))?;
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut builder = Builder::new(base_dir);
+    builder.run(&text, 0)?;
+    Ok(builder.finish())
+}
+
+// Tokens
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    // Quoted string
+    Text(String),
+    // Bare word: a directive, or true and false
+    Word(String),
+    Open,
+    Close,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_column) = (line, column);
+        let mut advance = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            let c = chars.next();
+            if c == Some('\n') {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+            c
+        };
+
+        let kind = match c {
+            c if c.is_whitespace() => {
+                advance(&mut chars);
+                continue;
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    advance(&mut chars);
+                }
+                continue;
+            }
+            '[' | ']' => {
+                advance(&mut chars);
+                if c == '[' { TokenKind::Open } else { TokenKind::Close }
+            }
+            '"' => {
+                advance(&mut chars);
+                let mut s = String::new();
+                loop {
+                    match advance(&mut chars) {
+                        Some('"') => break,
+                        Some('\\') => match advance(&mut chars) {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return error(start_line, start_column, "unterminated string".to_string()),
+                        },
+                        Some('\n') | None => return error(start_line, start_column, "unterminated string".to_string()),
+                        Some(c) => s.push(c),
+                    }
+                }
+                TokenKind::Text(s)
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '[' | ']' | '"' | '#') {
+                        break;
+                    }
+                    word.push(c);
+                    advance(&mut chars);
+                }
+                match word.parse::<f64>() {
+                    Ok(n) => TokenKind::Number(n),
+                    Err(_) => TokenKind::Word(word),
+                }
+            }
+        };
+        tokens.push(Token { kind, line: start_line, column: start_column });
+    }
+    Ok(tokens)
+}
+
+// Statements
+
+// A directive and the tokens up to the next one
+struct Statement {
+    keyword: String,
+    line: usize,
+    column: usize,
+    args: Vec<Token>,
+}
+
+fn is_directive(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Word(w) if w != "true" && w != "false")
+}
+
+fn statements(tokens: Vec<Token>) -> Result<Vec<Statement>> {
+    let mut statements: Vec<Statement> = Vec::new();
+    for token in tokens {
+        match (&token.kind, statements.last_mut()) {
+            (TokenKind::Word(w), _) if is_directive(&token.kind) => {
+                statements.push(Statement { keyword: w.clone(), line: token.line, column: token.column, args: Vec::new() })
+            }
+            (_, Some(statement)) => statement.args.push(token),
+            (_, None) => return error(token.line, token.column, "expected a directive".to_string()),
+        }
+    }
+    Ok(statements)
+}
+
+impl Statement {
+    fn fail<T>(&self, message: String) -> Result<T> {
+        error(self.line, self.column, message)
+    }
+
+    fn warn(&self, message: &str) {
+        warn(self.line, self.column, message);
+    }
+
+    // Exactly `count` numbers, with or without brackets around them
+    fn numbers(&self, count: usize) -> Result<Vec<f64>> {
+        let mut numbers = Vec::new();
+        for token in &self.args {
+            match token.kind {
+                TokenKind::Number(n) => numbers.push(n),
+                TokenKind::Open | TokenKind::Close => {}
+                _ => return error(token.line, token.column, format!("{} takes only numbers", self.keyword)),
+            }
+        }
+        if numbers.len() != count {
+            return self.fail(format!("{} takes {} numbers, got {}", self.keyword, count, numbers.len()));
+        }
+        Ok(numbers)
+    }
+
+    fn vector(&self, values: &[f64]) -> Vec3 {
+        Vec3::new(values[0], values[1], values[2])
+    }
+
+    // `count` leading strings, then the parameter list
+    fn split(&self, count: usize) -> Result<(Vec<String>, ParamSet)> {
+        let mut strings = Vec::new();
+        for i in 0..count {
+            match self.args.get(i).map(|t| &t.kind) {
+                Some(TokenKind::Text(s)) => strings.push(s.clone()),
+                _ => return self.fail(format!("{} needs {} quoted string argument(s)", self.keyword, count)),
+            }
+        }
+        Ok((strings, ParamSet::parse(&self.args[count..])?))
+    }
+}
+
+// Parameter lists: "type name" value or "type name" [ values ]
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Value>,
+    line: usize,
+    column: usize,
+}
+
+struct ParamSet {
+    params: Vec<Param>,
+}
+
+fn value(token: &Token) -> Result<Value> {
+    match &token.kind {
+        TokenKind::Number(n) => Ok(Value::Number(*n)),
+        TokenKind::Text(s) => Ok(Value::Text(s.clone())),
+        TokenKind::Word(w) if w == "true" => Ok(Value::Bool(true)),
+        TokenKind::Word(w) if w == "false" => Ok(Value::Bool(false)),
+        _ => error(token.line, token.column, "expected a parameter value".to_string()),
+    }
+}
+
+impl ParamSet {
+    fn parse(tokens: &[Token]) -> Result<ParamSet> {
+        let mut params = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let TokenKind::Text(declaration) = &token.kind else {
+                return error(token.line, token.column, "expected a parameter like \"float radius\"".to_string());
+            };
+            let parts: Vec<&str> = declaration.split_whitespace().collect();
+            let [ty, name] = parts[..] else {
+                return error(token.line, token.column, format!("bad parameter declaration \"{}\"", declaration));
+            };
+
+            let mut values = Vec::new();
+            i += 1;
+            match tokens.get(i).map(|t| &t.kind) {
+                Some(TokenKind::Open) => {
+                    i += 1;
+                    loop {
+                        match tokens.get(i) {
+                            Some(Token { kind: TokenKind::Close, .. }) => break,
+                            Some(t) => values.push(value(t)?),
+                            None => return error(token.line, token.column, format!("missing ']' for \"{}\"", declaration)),
+                        }
+                        i += 1;
+                    }
+                }
+                Some(_) => values.push(value(&tokens[i])?),
+                None => return error(token.line, token.column, format!("\"{}\" needs a value", declaration)),
+            }
+            i += 1;
+
+            params.push(Param { ty: ty.to_string(), name: name.to_string(), values, line: token.line, column: token.column });
+        }
+        Ok(ParamSet { params })
+    }
+
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Result<Option<Vec<f64>>> {
+        let Some(p) = self.find(name) else { return Ok(None) };
+        p.values
+            .iter()
+            .map(|v| match v {
+                Value::Number(n) => Ok(*n),
+                _ => error(p.line, p.column, format!("\"{} {}\" takes numbers", p.ty, p.name)),
+            })
+            .collect::<Result<Vec<f64>>>()
+            .map(Some)
+    }
+
+    fn float(&self, name: &str, default: f64) -> Result<f64> {
+        match self.numbers(name)? {
+            Some(values) if !values.is_empty() => Ok(values[0]),
+            _ => Ok(default),
+        }
+    }
+
+    fn point(&self, name: &str, default: Point3) -> Result<Point3> {
+        match self.numbers(name)? {
+            Some(v) if v.len() == 3 => Ok(Point3::new(v[0], v[1], v[2])),
+            Some(_) => self.fail(name, "takes 3 numbers"),
+            None => Ok(default),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>> {
+        match self.find(name).map(|p| p.values.as_slice()) {
+            Some([Value::Text(s)]) => Ok(Some(s.clone())),
+            Some(_) => self.fail(name, "takes one string"),
+            None => Ok(None),
+        }
+    }
+
+    fn bool(&self, name: &str, default: bool) -> Result<bool> {
+        match self.find(name).map(|p| p.values.as_slice()) {
+            Some([Value::Bool(b)]) => Ok(*b),
+            Some([Value::Text(s)]) if s == "true" || s == "false" => Ok(s == "true"),
+            Some(_) => self.fail(name, "takes true or false"),
+            None => Ok(default),
+        }
+    }
+
+    // A color given as rgb, or a float for gray. Other spectra can't be converted and are
+    // skipped with a warning.
+    fn color(&self, name: &str) -> Result<Option<Color>> {
+        let Some(p) = self.find(name) else { return Ok(None) };
+        match p.ty.as_str() {
+            "rgb" | "color" => match self.numbers(name)?.as_deref() {
+                Some([r, g, b]) => Ok(Some(Color::new(*r, *g, *b))),
+                _ => self.fail(name, "takes 3 numbers"),
+            },
+            "float" => Ok(Some(Color::new(1.0, 1.0, 1.0) * self.float(name, 1.0)?)),
+            _ => {
+                warn(p.line, p.column, &format!("\"{} {}\" is not supported; using the default", p.ty, p.name));
+                Ok(None)
+            }
+        }
+    }
+
+    // Name of a named spectrum, like "metal-Au-eta"
+    fn spectrum_name(&self, name: &str) -> Option<String> {
+        match self.find(name) {
+            Some(Param { ty, values, .. }) if ty == "spectrum" => match values.as_slice() {
+                [Value::Text(s)] => Some(s.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn fail<T>(&self, name: &str, message: &str) -> Result<T> {
+        let p = self.find(name).expect("failing parameter exists");
+        error(p.line, p.column, format!("\"{} {}\" {}", p.ty, p.name, message))
+    }
+}
+
+// Scene building
+
+// Attributes saved by AttributeBegin and restored by AttributeEnd
+#[derive(Clone)]
+struct GraphicsState {
+    // Object space to pbrt world space
+    ctm: Transform,
+    // None for pbrt's "interface" material, whose shapes are invisible
+    material: Option<Arc<dyn Material>>,
+    // Radiance and two-sidedness for the shapes that follow
+    area_light: Option<(Color, bool)>,
+    reverse_orientation: bool,
+}
+
+struct Builder {
+    base_dir: PathBuf,
+    camera: Camera,
+    fov: f64,
+    resolution: (i32, i32),
+    world: Vec<Arc<dyn Hittable>>,
+    state: GraphicsState,
+    attribute_stack: Vec<GraphicsState>,
+    transform_stack: Vec<Transform>,
+    named_materials: HashMap<String, Option<Arc<dyn Material>>>,
+    coordinate_systems: HashMap<String, Transform>,
+    objects: HashMap<String, Arc<dyn Hittable>>,
+    // Name and shapes of the object between ObjectBegin and ObjectEnd
+    current_object: Option<(String, Vec<Arc<dyn Hittable>>)>,
+}
+
+impl Builder {
+    fn new(base_dir: PathBuf) -> Self {
+        // pbrt's defaults, which differ from the camera's own
+        let mut camera = Camera::new();
+        camera.samples_per_pixel = 16;
+        camera.max_depth = 5;
+        camera.background = Some(Color::new(0.0, 0.0, 0.0));
+        camera.defocus_angle = 0.0;
+
+        Self {
+            base_dir,
+            camera,
+            fov: 90.0,
+            resolution: (1280, 720),
+            world: Vec::new(),
+            state: GraphicsState {
+                ctm: Transform::identity(),
+                material: Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+                area_light: None,
+                reverse_orientation: false,
+            },
+            attribute_stack: Vec::new(),
+            transform_stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            objects: HashMap::new(),
+            current_object: None,
+        }
+    }
+
+    fn run(&mut self, text: &str, depth: usize) -> Result<()> {
+        for statement in statements(tokenize(text)?)? {
+            self.statement(&statement, depth)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Scene {
+        let (width, height) = self.resolution;
+        self.camera.set_image_size(width, height);
+        // pbrt's fov spans the shorter side of the image
+        self.camera.vfov = if width >= height {
+            self.fov
+        } else {
+            2.0 * ((self.fov.to_radians() / 2.0).tan() * height as f64 / width as f64).atan().to_degrees()
+        };
+
+        let world = HittableList::new(vec![Arc::new(BvhNode::new(self.world))]);
+        Scene { world, camera: self.camera }
+    }
+
+    // Object space to the renderer's world
+    fn render_from_object(&self) -> Transform {
+        let t = self.state.ctm;
+        if self.current_object.is_some() { t } else { t.then(&mirror()) }
+    }
+
+    fn concat(&mut self, t: Transform) {
+        self.state.ctm = t.then(&self.state.ctm);
+    }
+
+    fn statement(&mut self, s: &Statement, depth: usize) -> Result<()> {
+        match s.keyword.as_str() {
+            "Identity" => {
+                s.numbers(0)?;
+                self.state.ctm = Transform::identity();
+            }
+            "Translate" => {
+                let v = s.numbers(3)?;
+                self.concat(Transform::translate(s.vector(&v)));
+            }
+            "Scale" => {
+                let v = s.numbers(3)?;
+                self.concat(Transform::scale(v[0], v[1], v[2]));
+            }
+            "Rotate" => {
+                let v = s.numbers(4)?;
+                self.concat(Transform::rotate(v[0], s.vector(&v[1..])));
+            }
+            "LookAt" => {
+                let v = s.numbers(9)?;
+                self.concat(look_at(s, s.vector(&v[0..3]), s.vector(&v[3..6]), s.vector(&v[6..9]))?);
+            }
+            "Transform" | "ConcatTransform" => {
+                let v = s.numbers(16)?;
+                // The numbers run down the columns
+                let mut m = [[0.0; 4]; 4];
+                for (i, row) in m.iter_mut().enumerate() {
+                    for (j, entry) in row.iter_mut().enumerate() {
+                        *entry = v[j * 4 + i];
+                    }
+                }
+                let Some(t) = Transform::from_matrix(m) else { return s.fail("singular transform".to_string()) };
+                if s.keyword == "Transform" {
+                    self.state.ctm = t;
+                } else {
+                    self.concat(t);
+                }
+            }
+            "CoordinateSystem" => {
+                let (args, _) = s.split(1)?;
+                self.coordinate_systems.insert(args[0].clone(), self.state.ctm);
+            }
+            "CoordSysTransform" => {
+                let (args, _) = s.split(1)?;
+                match self.coordinate_systems.get(&args[0]) {
+                    Some(t) => self.state.ctm = *t,
+                    None => s.warn(&format!("no coordinate system named '{}'", args[0])),
+                }
+            }
+            "ReverseOrientation" => self.state.reverse_orientation = !self.state.reverse_orientation,
+            "TransformBegin" => self.transform_stack.push(self.state.ctm),
+            "TransformEnd" => match self.transform_stack.pop() {
+                Some(t) => self.state.ctm = t,
+                None => return s.fail("TransformEnd without TransformBegin".to_string()),
+            },
+            "AttributeBegin" => self.attribute_stack.push(self.state.clone()),
+            "AttributeEnd" => match self.attribute_stack.pop() {
+                Some(state) => self.state = state,
+                None => return s.fail("AttributeEnd without AttributeBegin".to_string()),
+            },
+            "WorldBegin" => {
+                self.state.ctm = Transform::identity();
+                self.coordinate_systems.insert("world".to_string(), self.state.ctm);
+            }
+            "Camera" => self.camera(s)?,
+            "Film" => {
+                let (_, params) = s.split(1)?;
+                let width = params.float("xresolution", self.resolution.0 as f64)? as i32;
+                let height = params.float("yresolution", self.resolution.1 as f64)? as i32;
+                if width < 1 || height < 1 {
+                    return s.fail("the film resolution must be at least 1 by 1".to_string());
+                }
+                self.resolution = (width, height);
+            }
+            "Sampler" => {
+                let (_, params) = s.split(1)?;
+                self.camera.samples_per_pixel = params.float("pixelsamples", 16.0)?.max(1.0) as i32;
+            }
+            "Integrator" => {
+                let (_, params) = s.split(1)?;
+                self.camera.max_depth = params.float("maxdepth", 5.0)?.max(1.0) as i32;
+            }
+            "Material" => {
+                let (args, params) = s.split(1)?;
+                self.state.material = material(s, &args[0], &params)?;
+            }
+            "MakeNamedMaterial" => {
+                let (args, params) = s.split(1)?;
+                let kind = params.string("type")?.unwrap_or_else(|| "diffuse".to_string());
+                let mat = material(s, &kind, &params)?;
+                self.named_materials.insert(args[0].clone(), mat);
+            }
+            "NamedMaterial" => {
+                let (args, _) = s.split(1)?;
+                match self.named_materials.get(&args[0]) {
+                    Some(mat) => self.state.material = mat.clone(),
+                    None => return s.fail(format!("no material named '{}'", args[0])),
+                }
+            }
+            "AreaLightSource" => {
+                let (args, params) = s.split(1)?;
+                if args[0] != "diffuse" {
+                    s.warn(&format!("area light \"{}\" is not supported", args[0]));
+                    return Ok(());
+                }
+                let l = params.color("L")?.unwrap_or(Color::new(1.0, 1.0, 1.0)) * params.float("scale", 1.0)?;
+                self.state.area_light = Some((l, params.bool("twosided", false)?));
+            }
+            "LightSource" => self.light(s)?,
+            "Shape" => self.shape(s)?,
+            "ObjectBegin" => {
+                let (args, _) = s.split(1)?;
+                if self.current_object.is_some() {
+                    return s.fail("ObjectBegin inside another object".to_string());
+                }
+                self.attribute_stack.push(self.state.clone());
+                self.current_object = Some((args[0].clone(), Vec::new()));
+            }
+            "ObjectEnd" => {
+                let Some((name, shapes)) = self.current_object.take() else {
+                    return s.fail("ObjectEnd without ObjectBegin".to_string());
+                };
+                self.objects.insert(name, Arc::new(BvhNode::new(shapes)));
+                if let Some(state) = self.attribute_stack.pop() {
+                    self.state = state;
+                }
+            }
+            "ObjectInstance" => {
+                let (args, _) = s.split(1)?;
+                let Some(object) = self.objects.get(&args[0]) else {
+                    return s.fail(format!("no object named '{}'", args[0]));
+                };
+                let instance = Arc::new(Instance::new(object.clone(), self.render_from_object()));
+                self.add(instance);
+            }
+            "Include" | "Import" => {
+                let (args, _) = s.split(1)?;
+                if depth >= 32 {
+                    return s.fail("files include each other too deeply".to_string());
+                }
+                let path = self.base_dir.join(&args[0]);
+                let text = fs::read_to_string(&path)
+                    .or_else(|e| s.fail(format!("can't read {}: {}", path.display(), e)))?;
+                self.run(&text, depth + 1)
+                    .or_else(|e| s.fail(format!("in {}: {}", path.display(), e)))?;
+            }
+            "Texture" | "MakeNamedMedium" | "MediumInterface" | "Attribute" => {
+                s.warn(&format!("{} is not supported and was skipped", s.keyword));
+            }
+            // Settings with no counterpart here, and markers pbrt-v4 no longer needs
+            "PixelFilter" | "ColorSpace" | "Option" | "Accelerator" | "TransformTimes" | "ActiveTransform"
+            | "WorldEnd" => {}
+            other => return s.fail(format!("unknown directive '{}'", other)),
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, s: &Statement) -> Result<()> {
+        let (args, params) = s.split(1)?;
+        if args[0] != "perspective" {
+            s.warn(&format!("\"{}\" cameras are not supported; using a perspective camera", args[0]));
+        }
+
+        // The transform so far takes the world into camera space, where the camera looks down +z
+        let world_from_camera = self.state.ctm.inverse();
+        self.coordinate_systems.insert("camera".to_string(), world_from_camera);
+        let render_from_camera = world_from_camera.then(&mirror());
+        let camera = &mut self.camera;
+        camera.lookfrom = render_from_camera.point(&Point3::new(0.0, 0.0, 0.0));
+        camera.lookat = camera.lookfrom + render_from_camera.vector(&Vec3::new(0.0, 0.0, 1.0)).unit_vector();
+        camera.vup = render_from_camera.vector(&Vec3::new(0.0, 1.0, 0.0));
+
+        self.fov = params.float("fov", 90.0)?;
+        let lens_radius = params.float("lensradius", 0.0)?;
+        let focal_distance = params.float("focaldistance", 1e6)?;
+        if lens_radius > 0.0 {
+            camera.focus_dist = focal_distance;
+            camera.defocus_angle = 2.0 * (lens_radius / focal_distance).atan().to_degrees();
+        }
+        Ok(())
+    }
+
+    fn light(&mut self, s: &Statement) -> Result<()> {
+        let (args, params) = s.split(1)?;
+        let scale = params.float("scale", 1.0)?;
+        if params.find("power").is_some() {
+            s.warn("light \"power\" is not supported; using the intensity as given");
+        }
+        let t = self.render_from_object();
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let light: Arc<dyn Light> = match args[0].as_str() {
+            "point" => {
+                let from = t.point(&params.point("from", Point3::new(0.0, 0.0, 0.0))?);
+                Arc::new(PointLight::new(from, params.color("I")?.unwrap_or(white) * scale))
+            }
+            "spot" => {
+                let from = params.point("from", Point3::new(0.0, 0.0, 0.0))?;
+                let to = params.point("to", Point3::new(0.0, 0.0, 1.0))?;
+                let cone = params.float("coneangle", 30.0)?;
+                let delta = params.float("conedeltaangle", 5.0)?;
+                let intensity = params.color("I")?.unwrap_or(white) * scale;
+                Arc::new(SpotLight::new(t.point(&from), t.vector(&(to - from)), intensity, cone, cone - delta))
+            }
+            "distant" => {
+                let from = params.point("from", Point3::new(0.0, 0.0, 0.0))?;
+                let to = params.point("to", Point3::new(0.0, 0.0, 1.0))?;
+                Arc::new(DirectionalLight::new(t.vector(&(to - from)), params.color("L")?.unwrap_or(white) * scale))
+            }
+            "infinite" => {
+                if params.find("filename").is_some() {
+                    s.warn("infinite light images are not supported; using the constant color");
+                }
+                self.camera.background = Some(params.color("L")?.unwrap_or(white) * scale);
+                return Ok(());
+            }
+            other => {
+                s.warn(&format!("\"{}\" lights are not supported and were skipped", other));
+                return Ok(());
+            }
+        };
+        self.camera.lights.push(light);
+        Ok(())
+    }
+
+    fn shape(&mut self, s: &Statement) -> Result<()> {
+        let (args, params) = s.split(1)?;
+        if self.state.material.is_none() && self.state.area_light.is_none() {
+            return Ok(());
+        }
+        let mat = self.state.material.clone();
+        let t = self.render_from_object();
+
+        // Each primitive separately, so that emissive ones can become lights one by one
+        let primitives: Vec<Arc<dyn Hittable>> = match args[0].as_str() {
+            "sphere" => {
+                let radius = params.float("radius", 1.0)?;
+                let sphere = Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), radius, mat.clone()));
+                vec![Arc::new(Instance::new(sphere, t))]
+            }
+            "trianglemesh" | "plymesh" => {
+                let mesh = if args[0] == "plymesh" { self.ply_mesh(s, &params)? } else { self.triangle_mesh(s, &params)? };
+                let mesh = Arc::new(self.orient(mesh, &t));
+                if self.state.area_light.is_none() || self.current_object.is_some() {
+                    self.add(Arc::new(make_mesh(mesh, mat)));
+                    return Ok(());
+                }
+                (0..mesh.indices.len()).map(|face| Arc::new(Triangle::new(mesh.clone(), face, mat.clone())) as Arc<dyn Hittable>).collect()
+            }
+            other => {
+                s.warn(&format!("\"{}\" shapes are not supported and were skipped", other));
+                return Ok(());
+            }
+        };
+
+        // pbrt doesn't allow area lights in instanced objects
+        let area_light = if self.current_object.is_some() { None } else { self.state.area_light };
+        match area_light {
+            Some((l, two_sided)) => {
+                let mut lights: Vec<Arc<dyn Hittable>> = Vec::new();
+                for shape in primitives {
+                    let light = if two_sided { DiffuseAreaLight::two_sided(shape, l) } else { DiffuseAreaLight::new(shape, l) };
+                    self.camera.lights.push(light.clone());
+                    lights.push(light);
+                }
+                self.add(Arc::new(BvhNode::new(lights)));
+            }
+            None => {
+                for shape in primitives {
+                    self.add(shape);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn triangle_mesh(&self, s: &Statement, params: &ParamSet) -> Result<TriangleMesh> {
+        let Some(p) = params.numbers("P")? else { return s.fail("trianglemesh needs \"point3 P\"".to_string()) };
+        if p.len() % 3 != 0 {
+            return params.fail("P", "needs 3 numbers per vertex");
+        }
+        let positions: Vec<Point3> = p.chunks(3).map(|c| Point3::new(c[0], c[1], c[2])).collect();
+        let n = positions.len();
+
+        let indices = match params.numbers("indices")? {
+            Some(indices) => indices,
+            None if n == 3 => vec![0.0, 1.0, 2.0],
+            None => return s.fail("trianglemesh needs \"integer indices\" unless it is one triangle".to_string()),
+        };
+        if indices.len() % 3 != 0 || indices.iter().any(|&i| i < 0.0 || i as usize >= n) {
+            return params.fail("indices", "must list 3 vertex indices per triangle, each less than the vertex count");
+        }
+        let indices = indices.chunks(3).map(|c| [c[0] as usize, c[1] as usize, c[2] as usize]).collect();
+        let mut mesh = TriangleMesh::new(positions, indices);
+
+        if let Some(normals) = params.numbers("N")? {
+            if normals.len() != 3 * n {
+                return params.fail("N", "needs one normal per vertex");
+            }
+            mesh = mesh.with_normals(normals.chunks(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect());
+        }
+        let uv_name = ["uv", "st"].into_iter().find(|name| params.find(name).is_some());
+        if let Some(uv_name) = uv_name {
+            let uvs = params.numbers(uv_name)?.unwrap_or_default();
+            if uvs.len() != 2 * n {
+                return params.fail(uv_name, "needs one pair per vertex");
+            }
+            mesh = mesh.with_uvs(uvs.chunks(2).map(|c| (c[0], c[1])).collect());
+        }
+        Ok(mesh)
+    }
+
+    fn ply_mesh(&self, s: &Statement, params: &ParamSet) -> Result<TriangleMesh> {
+        let Some(filename) = params.string("filename")? else { return s.fail("plymesh needs \"string filename\"".to_string()) };
+        if params.find("displacement").is_some() {
+            s.warn("plymesh displacement is not supported; the mesh is used as it is");
+        }
+        let path = self.base_dir.join(&filename);
+        ply::load(&path).or_else(|e| s.fail(format!("can't load {}: {}", path.display(), e)))
+    }
+
+    // Takes a mesh into the renderer's world with its faces the right way out
+    fn orient(&self, mut mesh: TriangleMesh, t: &Transform) -> TriangleMesh {
+        // Faces wound counterclockwise in pbrt's left-handed world are wound clockwise once
+        // mirrored, so the winding flips unless the transform or the orientation flips it back
+        mesh.transform(t);
+        if self.state.reverse_orientation != (t.determinant() < 0.0) {
+            mesh.flip_winding();
+        }
+        mesh
+    }
+
+    fn add(&mut self, object: Arc<dyn Hittable>) {
+        match &mut self.current_object {
+            Some((_, shapes)) => shapes.push(object),
+            None => self.world.push(object),
+        }
+    }
+}
+
+// pbrt's LookAt: world to camera space, with the camera at `eye` looking down +z at `look`
+fn look_at(s: &Statement, eye: Point3, look: Point3, up: Vec3) -> Result<Transform> {
+    let dir = (look - eye).unit_vector();
+    let right = up.unit_vector().cross(&dir);
+    if right.near_zero() {
+        return s.fail("LookAt's up vector is parallel to the viewing direction".to_string());
+    }
+    let right = right.unit_vector();
+    let new_up = dir.cross(&right);
+    let m = [
+        [right.x(), new_up.x(), dir.x(), eye.x()],
+        [right.y(), new_up.y(), dir.y(), eye.y()],
+        [right.z(), new_up.z(), dir.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    let world_from_camera = Transform::from_matrix(m).expect("an orthonormal frame can be inverted");
+    Ok(world_from_camera.inverse())
+}
+
+// pbrt's roughness, which is remapped to the microfacet alpha by a square root unless
+// "remaproughness" is off, as this renderer's roughness, whose square is alpha
+fn roughness(params: &ParamSet) -> Result<f64> {
+    let r = match params.find("roughness") {
+        Some(_) => params.float("roughness", 0.0)?,
+        None => 0.5 * (params.float("uroughness", 0.0)? + params.float("vroughness", 0.0)?),
+    };
+    let alpha = if params.bool("remaproughness", true)? { r.max(0.0).sqrt() } else { r.max(0.0) };
+    Ok(alpha.sqrt())
+}
+
+fn material(s: &Statement, kind: &str, params: &ParamSet) -> Result<Option<Arc<dyn Material>>> {
+    let gray = Color::new(0.5, 0.5, 0.5);
+    let mat: Arc<dyn Material> = match kind {
+        "diffuse" => Arc::new(Lambertian::new(params.color("reflectance")?.unwrap_or(gray))),
+        "conductor" => {
+            let roughness = roughness(params)?;
+            if let Some(reflectance) = params.color("reflectance")? {
+                return Ok(Some(Arc::new(Metal::new(reflectance, roughness))));
+            }
+            // pbrt's default conductor is copper
+            let metal = match (params.spectrum_name("eta").as_deref(), params.find("eta")) {
+                (Some("metal-Au-eta"), _) => Metal::gold(roughness),
+                (Some("metal-Ag-eta"), _) => Metal::silver(roughness),
+                (Some("metal-Al-eta"), _) => Metal::aluminium(roughness),
+                (Some("metal-Cu-eta"), _) | (_, None) => Metal::copper(roughness),
+                _ => match (params.color("eta")?, params.color("k")?) {
+                    (Some(eta), Some(k)) => Metal::conductor(eta, k, roughness),
+                    _ => {
+                        s.warn("conductor spectra other than rgb and pbrt's named metals aren't supported; using copper");
+                        Metal::copper(roughness)
+                    }
+                },
+            };
+            Arc::new(metal)
+        }
+        "dielectric" | "thindielectric" => {
+            let roughness = roughness(params)?;
+            let ior = match params.spectrum_name("eta") {
+                Some(name) if name == "glass-BK7" && roughness == 0.0 => return Ok(Some(Arc::new(Dielectric::bk7()))),
+                Some(name) => {
+                    s.warn(&format!("spectrum \"{}\" is approximated by an index of refraction of 1.5", name));
+                    1.5
+                }
+                None => params.float("eta", 1.5)?,
+            };
+            if roughness > 0.0 { Arc::new(Dielectric::rough(ior, roughness)) } else { Arc::new(Dielectric::new(ior)) }
+        }
+        "interface" | "" | "none" => return Ok(None),
+        other => {
+            s.warn(&format!("\"{}\" materials are not supported; using a diffuse material", other));
+            Arc::new(Lambertian::new(params.color("reflectance")?.unwrap_or(gray)))
+        }
+    };
+    Ok(Some(mat))
+}
@@ -0,0 +1,306 @@
+use crate::rtweekend::*;
+use crate::triangle::TriangleMesh;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+// Reader for PLY polygon files, in ASCII or binary of either byte order.
+//
+// Vertices give positions (x, y, z) and optionally normals (nx, ny, nz), colors (red, green,
+// blue) and texture coordinates (u, v, or s, t, with or without a texture_ prefix). Faces list
+// their vertices as `vertex_indices` or `vertex_index`; polygons with more than three corners
+// are split into triangles around their first corner. Other elements and properties are read
+// past and ignored.
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid(&format!("unknown PLY property type '{}'", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+struct Property {
+    name: String,
+    // Type of the length of a list property
+    list: Option<Scalar>,
+    value: Scalar,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Values in the file's body, read one at a time
+struct Body<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64> {
+        if self.format == Format::Ascii {
+            while self.bytes.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.bytes.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if start == self.pos {
+                return Err(invalid("PLY file ends before all its elements"));
+            }
+            let word = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+            return word.parse().map_err(|_| invalid(&format!("bad number '{}' in PLY file", word)));
+        }
+
+        let size = ty.size();
+        let Some(b) = self.bytes.get(self.pos..self.pos + size) else {
+            return Err(invalid("PLY file ends before all its elements"));
+        };
+        self.pos += size;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(b);
+        if self.format == Format::BigEndian {
+            buf[..size].reverse();
+        }
+        Ok(match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<TriangleMesh> {
+    parse(&fs::read(path)?)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<TriangleMesh> {
+    let (format, elements, body_start) = header(bytes)?;
+    let mut body = Body { bytes, pos: body_start, format };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    // Items of the list property last read, reused from one list to the next
+    let mut items = Vec::new();
+
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| p.list.is_none() && names.contains(&p.name.as_str()));
+        let slots = |names: [&[&str]; 3]| -> Option<[usize; 3]> { Some([find(names[0])?, find(names[1])?, find(names[2])?]) };
+
+        match element.name.as_str() {
+            "vertex" => {
+                let Some(position) = slots([&["x"], &["y"], &["z"]]) else {
+                    return Err(invalid("PLY vertices need x, y and z"));
+                };
+                let normal = slots([&["nx"], &["ny"], &["nz"]]);
+                let color = slots([&["red", "r"], &["green", "g"], &["blue", "b"]]);
+                let uv = [
+                    &["u", "s", "texture_u", "texture_s"][..],
+                    &["v", "t", "texture_v", "texture_t"][..],
+                ];
+                let uv = find(uv[0]).zip(find(uv[1]));
+                // Integer colors run up to the type's maximum, float ones up to 1
+                let color_scale = match color.map(|c| element.properties[c[0]].value) {
+                    Some(Scalar::U8) => 1.0 / 255.0,
+                    Some(Scalar::U16) => 1.0 / 65535.0,
+                    _ => 1.0,
+                };
+
+                let mut row = vec![0.0; element.properties.len()];
+                for _ in 0..element.count {
+                    for (slot, property) in row.iter_mut().zip(&element.properties) {
+                        *slot = read_property(&mut body, property, &mut items)?;
+                    }
+                    let vec = |s: [usize; 3]| Vec3::new(row[s[0]], row[s[1]], row[s[2]]);
+                    positions.push(vec(position));
+                    if let Some(n) = normal {
+                        normals.push(vec(n));
+                    }
+                    if let Some(c) = color {
+                        colors.push(color_scale * vec(c));
+                    }
+                    if let Some((u, v)) = uv {
+                        uvs.push((row[u], row[v]));
+                    }
+                }
+            }
+            "face" => {
+                let list = element.properties.iter().position(|p| p.list.is_some() && matches!(p.name.as_str(), "vertex_indices" | "vertex_index"));
+                let Some(list) = list else { return Err(invalid("PLY faces need a vertex_indices list")) };
+                for _ in 0..element.count {
+                    for (k, property) in element.properties.iter().enumerate() {
+                        read_property(&mut body, property, &mut items)?;
+                        if k != list {
+                            continue;
+                        }
+                        if items.iter().any(|&i| i < 0.0 || i.fract() != 0.0) {
+                            return Err(invalid("PLY face has a negative or fractional vertex index"));
+                        }
+                        for k in 1..items.len().saturating_sub(1) {
+                            indices.push([items[0] as usize, items[k] as usize, items[k + 1] as usize]);
+                        }
+                    }
+                }
+            }
+            _ => {
+                // Other elements only need reading past
+                if format != Format::Ascii && element.properties.iter().all(|p| p.list.is_none()) {
+                    body.pos += element.count * element.properties.iter().map(|p| p.value.size()).sum::<usize>();
+                } else {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            read_property(&mut body, property, &mut items)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if indices.iter().flatten().any(|&i| i >= positions.len()) {
+        return Err(invalid("PLY face refers to a vertex past the last one"));
+    }
+    let mut mesh = TriangleMesh::new(positions, indices);
+    if !normals.is_empty() {
+        mesh = mesh.with_normals(normals);
+    }
+    if !uvs.is_empty() {
+        mesh = mesh.with_uvs(uvs);
+    }
+    if !colors.is_empty() {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+// A property's value. A list property's items replace the contents of `items` instead, and its
+// length is returned.
+fn read_property(body: &mut Body, property: &Property, items: &mut Vec<f64>) -> Result<f64> {
+    let Some(count_type) = property.list else {
+        return body.read(property.value);
+    };
+    let count = body.read(count_type)?;
+    if count < 0.0 || count.fract() != 0.0 {
+        return Err(invalid("bad list length in PLY file"));
+    }
+    items.clear();
+    for _ in 0..count as usize {
+        items.push(body.read(property.value)?);
+    }
+    Ok(count)
+}
+
+// Format, elements and the offset of the first byte after the header
+fn header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize)> {
+    let mut pos = 0;
+    let mut next_line = || -> Result<String> {
+        let start = pos;
+        while pos < bytes.len() && bytes[pos] != b'\n' {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return Err(invalid("PLY header has no end_header"));
+        }
+        pos += 1;
+        Ok(String::from_utf8_lossy(&bytes[start..pos]).trim().to_string())
+    };
+
+    if next_line()? != "ply" {
+        return Err(invalid("missing PLY signature"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let line = next_line()?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(invalid(&format!("unknown PLY format '{}'", kind))),
+                });
+            }
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| invalid("bad PLY element count"))?;
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            }
+            ["property", "list", count_type, value_type, name] => {
+                let count_type = Scalar::parse(count_type)?;
+                if !count_type.is_integer() {
+                    return Err(invalid("PLY list lengths must be integers"));
+                }
+                let property = Property { name: name.to_string(), list: Some(count_type), value: Scalar::parse(value_type)? };
+                elements.last_mut().ok_or_else(|| invalid("PLY property before any element"))?.properties.push(property);
+            }
+            ["property", value_type, name] => {
+                let property = Property { name: name.to_string(), list: None, value: Scalar::parse(value_type)? };
+                elements.last_mut().ok_or_else(|| invalid("PLY property before any element"))?.properties.push(property);
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid(&format!("unexpected line in PLY header: {}", line))),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("PLY header has no format line"))?;
+    Ok((format, elements, pos))
+}
@@ -0,0 +1,356 @@
+use crate::color::srgb_to_linear;
+use crate::hdr_image::HdrImage;
+use crate::rtweekend::*;
+use std::io::{Error, ErrorKind, Result};
+
+// A decoded PNG: every pixel as red, green, blue and alpha in [0,1], rows from the top.
+//
+// All color types and bit depths are read, with palette transparency. Interlaced images are
+// not supported.
+pub struct PngImage {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[f64; 4]>,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+impl PngImage {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Err(invalid("missing PNG signature"));
+        }
+
+        let mut pos = 8;
+        let mut header = None;
+        let mut palette: Vec<[f64; 4]> = Vec::new();
+        let mut transparency: Vec<u8> = Vec::new();
+        let mut compressed = Vec::new();
+        while pos + 8 <= bytes.len() {
+            let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = &bytes[pos + 4..pos + 8];
+            let data = bytes.get(pos + 8..pos + 8 + length).ok_or_else(|| invalid("truncated PNG chunk"))?;
+            pos += 12 + length;
+            match kind {
+                b"IHDR" => {
+                    if data.len() < 13 {
+                        return Err(invalid("short PNG header"));
+                    }
+                    let width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+                    let height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                    let (depth, color_type, interlace) = (data[8], data[9], data[12]);
+                    if interlace != 0 {
+                        return Err(invalid("interlaced PNGs are not supported"));
+                    }
+                    header = Some((width, height, depth, color_type));
+                }
+                b"PLTE" => {
+                    palette = data.chunks_exact(3)
+                        .map(|c| [c[0] as f64 / 255.0, c[1] as f64 / 255.0, c[2] as f64 / 255.0, 1.0])
+                        .collect();
+                }
+                b"tRNS" => transparency = data.to_vec(),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+
+        let Some((width, height, depth, color_type)) = header else { return Err(invalid("missing PNG header")) };
+        let channels = match (color_type, depth) {
+            (0, 1 | 2 | 4 | 8 | 16) => 1,
+            (3, 1 | 2 | 4 | 8) => 1,
+            (2 | 4 | 6, 8 | 16) => [0, 0, 3, 0, 2, 0, 4][color_type as usize],
+            _ => return Err(invalid("unsupported PNG color type or bit depth")),
+        };
+        for (entry, &alpha) in palette.iter_mut().zip(&transparency) {
+            entry[3] = alpha as f64 / 255.0;
+        }
+
+        let bits_per_pixel = channels * depth as usize;
+        let stride = (width * bits_per_pixel).div_ceil(8);
+        let raw = zlib_decompress(&compressed)?;
+        if raw.len() < height * (stride + 1) {
+            return Err(invalid("PNG image data is too short"));
+        }
+        let rows = unfilter(&raw, height, stride, bits_per_pixel.div_ceil(8))?;
+
+        let max = ((1u32 << depth) - 1) as f64;
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in rows.chunks_exact(stride.max(1)).take(height) {
+            let sample = |index: usize| -> u32 {
+                match depth {
+                    16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as u32,
+                    8 => row[index] as u32,
+                    _ => {
+                        let bit = index * depth as usize;
+                        let shift = 8 - depth as usize - bit % 8;
+                        (row[bit / 8] as u32 >> shift) & ((1 << depth) - 1)
+                    }
+                }
+            };
+            for x in 0..width {
+                let s = |c: usize| sample(x * channels + c) as f64 / max;
+                pixels.push(match color_type {
+                    0 => [s(0), s(0), s(0), 1.0],
+                    2 => [s(0), s(1), s(2), 1.0],
+                    3 => *palette.get(sample(x) as usize).ok_or_else(|| invalid("PNG palette index out of range"))?,
+                    4 => [s(0), s(0), s(0), s(1)],
+                    _ => [s(0), s(1), s(2), s(3)],
+                });
+            }
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    // Color channels, decoded from sRGB to linear for color textures, or kept as stored for
+    // data such as normal maps
+    pub fn color(&self, srgb: bool) -> HdrImage {
+        let decode = |c: f64| if srgb { srgb_to_linear(c) } else { c };
+        let data = self.pixels.iter().map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2]))).collect();
+        HdrImage::new(self.width, self.height, data)
+    }
+
+    // Alpha channel, in all three channels of the image
+    pub fn alpha(&self) -> HdrImage {
+        let data = self.pixels.iter().map(|p| Color::new(p[3], p[3], p[3])).collect();
+        HdrImage::new(self.width, self.height, data)
+    }
+}
+
+// Undoes the per-row filters, giving the rows back to back without their filter bytes
+fn unfilter(raw: &[u8], height: usize, stride: usize, bpp: usize) -> Result<Vec<u8>> {
+    let mut out = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, rest) = out.split_at_mut(y * stride);
+        let prior = if y > 0 { &done[(y - 1) * stride..] } else { &[][..] };
+        let current = &mut rest[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = prior.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp { prior.get(x - bpp).copied().unwrap_or(0) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid("bad PNG filter type")),
+            };
+            current[x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Inflate
+
+fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 2 || data[0] & 0x0f != 8 || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) {
+        return Err(invalid("bad zlib header in PNG data"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid("PNG data uses a preset dictionary"));
+    }
+    inflate(&data[2..])
+}
+
+// Reads bits least significant first, as deflate packs them
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or_else(|| invalid("truncated deflate stream"))?;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+            self.pos += 1;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer = if n == 32 { 0 } else { self.buffer >> n };
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let drop = self.count % 8;
+        self.buffer >>= drop;
+        self.count -= drop;
+    }
+}
+
+// Canonical Huffman code, decoded one bit at a time: the number of codes of each length and
+// the symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code in deflate stream"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] =
+    [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] =
+    [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order the code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Raw deflate (RFC 1951) with stored, fixed and dynamic Huffman blocks
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut bits = BitReader { data, pos: 0, buffer: 0, count: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align_to_byte();
+                let len = bits.bits(16)?;
+                let nlen = bits.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("corrupt stored block in deflate stream"));
+                }
+                for _ in 0..len {
+                    out.push(bits.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(&mut bits, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(invalid("bad block type in deflate stream")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| invalid("bad code lengths in deflate stream"))?;
+                (previous, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("bad code lengths in deflate stream"));
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(bits: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = literals.decode(bits)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(invalid("bad length code in deflate stream"));
+                }
+                let length = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+                let d = distances.decode(bits)?;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(invalid("bad distance code in deflate stream"));
+                }
+                let distance = DISTANCE_BASE[d] as usize + bits.bits(DISTANCE_EXTRA[d] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid("distance too far back in deflate stream"));
+                }
+                // Copied byte by byte, since the source may overlap what is being written
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::light::DiffuseAreaLight;
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::quad::{make_box, Quad};
+use crate::rtweekend::*;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use std::sync::Arc;
+
+// Built-in scenes, picked by name, for trying the renderer without writing a scene file
+pub const PRESETS: &[(&str, &str)] = &[
+    ("weekend-final", "the random spheres from the cover of Ray Tracing in One Weekend"),
+    ("five-spheres", "ground, diffuse, glass with an air bubble and fuzzy metal spheres"),
+    ("checkered-spheres", "two spheres sharing a 3D checker texture"),
+    ("perlin-spheres", "marble-like Perlin noise on a sphere and the ground"),
+    ("earth", "a globe with an image texture, read from images/earthmap.png"),
+    ("cornell-box", "the Cornell box with two rotated blocks"),
+    ("next-week-final", "the final scene of Ray Tracing: The Next Week"),
+    ("material-grid", "rows of glass and metal spheres with increasing IOR and roughness"),
+];
+
+pub const DEFAULT_PRESET: &str = "weekend-final";
+
+// Builds the preset called `name`, or None if there is no such preset. Scenes made with random
+// numbers, such as the random spheres, draw them from `sampler`.
+pub fn build(name: &str, sampler: &mut Sampler) -> Option<Scene> {
+    let scene = match name {
+        "weekend-final" => weekend_final(sampler),
+        "five-spheres" => five_spheres(),
+        "checkered-spheres" => checkered_spheres(),
+        "perlin-spheres" => perlin_spheres(sampler),
+        "earth" => earth(),
+        "cornell-box" => cornell_box(),
+        "next-week-final" => next_week_final(sampler),
+        "material-grid" => material_grid(),
+        _ => return None,
+    };
+    Some(scene)
+}
+
+fn sphere(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Arc<dyn Hittable> {
+    Arc::new(Sphere::new(center, radius, Some(mat)))
+}
+
+// Adds a one-sided area light to both the world and the camera's lights
+fn add_light(world: &mut HittableList, camera: &mut Camera, shape: Arc<dyn Hittable>, emit: Color) {
+    let light = DiffuseAreaLight::new(shape, emit);
+    camera.lights.push(light.clone());
+    world.add(light);
+}
+
+// The earth texture map, the book's image converted to PNG since JPEG can't be read. The image
+// isn't shipped, so without it the globe is a plain ocean blue and a warning says why.
+fn earth_texture() -> Arc<dyn Texture> {
+    let path = "images/earthmap.png";
+    match ImageTexture::load(path) {
+        Ok(texture) => Arc::new(texture),
+        Err(e) => {
+            eprintln!("warning: {}: {}; using a plain globe", path, e);
+            Arc::new(SolidColor::new(Color::new(0.1, 0.25, 0.5)))
+        }
+    }
+}
+
+fn weekend_final(sampler: &mut Sampler) -> Scene {
+    let mut world = HittableList::new(Vec::new());
+
+    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_material));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = sampler.next_f64();
+            let center = Point3::new(a as f64 + 0.9 * sampler.next_f64(), 0.2, b as f64 + 0.9 * sampler.next_f64());
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random(sampler) * Color::random(sampler);
+                    Arc::new(Lambertian::new(albedo))
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_range(sampler, 0.5, 1.0);
+                    let fuzz = sampler.range(0.0, 0.5);
+                    Arc::new(Metal::new(albedo, fuzz))
+                } else {
+                    // glass
+                    Arc::new(Dielectric::new(1.5))
+                };
+                world.add(sphere(center, 0.2, sphere_material));
+            }
+        }
+    }
+
+    world.add(sphere(Point3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Dielectric::new(1.5))));
+    world.add(sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)))));
+    world.add(sphere(Point3::new(4.0, 1.0, 0.0), 1.0, Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0))));
+    let world = HittableList::new(vec![Arc::new(BvhNode::from_list(&world))]);
+
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 500;
+    camera.max_depth = 50;
+    camera.vfov = 20.0;
+    camera.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    camera.lookat = Point3::new(0.0, 0.0, 0.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 0.6;
+    camera.focus_dist = 10.0;
+
+    Scene { world, camera }
+}
+
+fn five_spheres() -> Scene {
+    let mut world = HittableList::new(Vec::new());
+
+    let material_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
+    let material_left = Arc::new(Dielectric::new(1.5));
+    let material_bubble = Arc::new(Dielectric::new(1.00 / 1.50));
+    let material_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
+
+    world.add(sphere(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground));
+    world.add(sphere(Point3::new(0.0, 0.0, -1.2), 0.5, material_center));
+    world.add(sphere(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left));
+    world.add(sphere(Point3::new(-1.0, 0.0, -1.0), 0.4, material_bubble));
+    world.add(sphere(Point3::new(1.0, 0.0, -1.0), 0.5, material_right));
+
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.vfov = 20.0;
+    camera.lookfrom = Point3::new(-2.0, 2.0, 1.0);
+    camera.lookat = Point3::new(0.0, 0.0, -1.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 10.0;
+    camera.focus_dist = 3.4;
+
+    Scene { world, camera }
+}
+
+// Camera shared by the two-sphere texture scenes
+fn texture_test_camera() -> Camera {
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.vfov = 20.0;
+    camera.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    camera.lookat = Point3::new(0.0, 0.0, 0.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 0.0;
+    camera
+}
+
+fn checkered_spheres() -> Scene {
+    let mut world = HittableList::new(Vec::new());
+
+    let checker = Arc::new(CheckerTexture::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    world.add(sphere(Point3::new(0.0, -10.0, 0.0), 10.0, Arc::new(Lambertian::from_texture(checker.clone()))));
+    world.add(sphere(Point3::new(0.0, 10.0, 0.0), 10.0, Arc::new(Lambertian::from_texture(checker))));
+
+    Scene { world, camera: texture_test_camera() }
+}
+
+fn perlin_spheres(sampler: &mut Sampler) -> Scene {
+    let mut world = HittableList::new(Vec::new());
+
+    let pertext = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(4.0, sampler))));
+    world.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, pertext.clone()));
+    world.add(sphere(Point3::new(0.0, 2.0, 0.0), 2.0, pertext));
+
+    Scene { world, camera: texture_test_camera() }
+}
+
+fn earth() -> Scene {
+    let earth_surface = Arc::new(Lambertian::from_texture(earth_texture()));
+    let world = HittableList::new(vec![sphere(Point3::new(0.0, 0.0, 0.0), 2.0, earth_surface)]);
+
+    let mut camera = texture_test_camera();
+    camera.lookfrom = Point3::new(0.0, 0.0, 12.0);
+
+    Scene { world, camera }
+}
+
+fn cornell_box() -> Scene {
+    let mut world = HittableList::new(Vec::new());
+    let mut camera = Camera::new();
+
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    let quad = |q: Point3, u: Vec3, v: Vec3, mat: &Arc<dyn Material>| -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(q, u, v, Some(mat.clone())))
+    };
+    world.add(quad(Point3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), &green));
+    world.add(quad(Point3::new(0.0, 0.0, 555.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, -555.0), &red));
+    world.add(quad(Point3::new(0.0, 0.0, 0.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), &white));
+    world.add(quad(Point3::new(555.0, 555.0, 555.0), Vec3::new(-555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -555.0), &white));
+    world.add(quad(Point3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), &white));
+
+    // Ceiling light, facing down into the box
+    let lamp = Quad::new(Point3::new(343.0, 554.0, 332.0), Vec3::new(-130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -105.0), None);
+    add_light(&mut world, &mut camera, Arc::new(lamp), Color::new(15.0, 15.0, 15.0));
+
+    let block = |height: f64, degrees: f64, offset: Vec3| -> Arc<dyn Hittable> {
+        let shape = make_box(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, height, 165.0), Some(white.clone()));
+        let placement = Transform::rotate(degrees, Vec3::new(0.0, 1.0, 0.0)).then(&Transform::translate(offset));
+        Arc::new(Instance::new(Arc::new(shape), placement))
+    };
+    world.add(block(330.0, 15.0, Vec3::new(265.0, 0.0, 295.0)));
+    world.add(block(165.0, -18.0, Vec3::new(130.0, 0.0, 65.0)));
+
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 600;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+    camera.background = Some(Color::new(0.0, 0.0, 0.0));
+    camera.vfov = 40.0;
+    camera.lookfrom = Point3::new(278.0, 278.0, -800.0);
+    camera.lookat = Point3::new(278.0, 278.0, 0.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 0.0;
+
+    Scene { world, camera }
+}
+
+// The book's scene has a motion-blurred sphere in the top left; it stands still here.
+fn next_week_final(sampler: &mut Sampler) -> Scene {
+    let mut world = HittableList::new(Vec::new());
+    let mut camera = Camera::new();
+
+    // A floor of boxes with random heights
+    let ground = Arc::new(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
+    let boxes_per_side = 20;
+    let mut boxes1: Vec<Arc<dyn Hittable>> = Vec::new();
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = sampler.range(1.0, 101.0);
+            let z1 = z0 + w;
+            boxes1.push(Arc::new(make_box(Point3::new(x0, y0, z0), Point3::new(x1, y1, z1), Some(ground.clone()))));
+        }
+    }
+    world.add(Arc::new(BvhNode::new(boxes1)));
+
+    let lamp = Quad::new(Point3::new(123.0, 554.0, 147.0), Vec3::new(300.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 265.0), None);
+    add_light(&mut world, &mut camera, Arc::new(lamp), Color::new(7.0, 7.0, 7.0));
+
+    world.add(sphere(Point3::new(400.0, 400.0, 200.0), 50.0, Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.1)))));
+    world.add(sphere(Point3::new(260.0, 150.0, 45.0), 50.0, Arc::new(Dielectric::new(1.5))));
+    world.add(sphere(Point3::new(0.0, 150.0, 145.0), 50.0, Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0))));
+
+    // A glass sphere filled with blue smoke, and a thin mist over the whole scene
+    let boundary = sphere(Point3::new(360.0, 150.0, 145.0), 70.0, Arc::new(Dielectric::new(1.5)));
+    world.add(boundary.clone());
+    world.add(Arc::new(ConstantMedium::new(boundary, 0.2, Color::new(0.2, 0.4, 0.9))));
+    let boundary = sphere(Point3::new(0.0, 0.0, 0.0), 5000.0, Arc::new(Dielectric::new(1.5)));
+    world.add(Arc::new(ConstantMedium::new(boundary, 0.0001, Color::new(1.0, 1.0, 1.0))));
+
+    let emat = Arc::new(Lambertian::from_texture(earth_texture()));
+    world.add(sphere(Point3::new(400.0, 200.0, 400.0), 100.0, emat));
+    let pertext = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(0.2, sampler))));
+    world.add(sphere(Point3::new(220.0, 280.0, 300.0), 80.0, pertext));
+
+    // A cube of small white spheres
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let boxes2: Vec<Arc<dyn Hittable>> =
+        (0..1000).map(|_| sphere(Point3::random_range(sampler, 0.0, 165.0), 10.0, white.clone())).collect();
+    let placement = Transform::rotate(15.0, Vec3::new(0.0, 1.0, 0.0)).then(&Transform::translate(Vec3::new(-100.0, 270.0, 395.0)));
+    world.add(Arc::new(Instance::new(Arc::new(BvhNode::new(boxes2)), placement)));
+
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 800;
+    camera.samples_per_pixel = 1000;
+    camera.max_depth = 40;
+    camera.background = Some(Color::new(0.0, 0.0, 0.0));
+    camera.vfov = 40.0;
+    camera.lookfrom = Point3::new(478.0, 278.0, -600.0);
+    camera.lookat = Point3::new(278.0, 278.0, 0.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 0.0;
+
+    Scene { world, camera }
+}
+
+// Five columns of spheres on a checkered floor under the sky. Front to back: glass of
+// increasing IOR, frosted glass of increasing roughness, then brushed aluminium and gold of
+// increasing roughness.
+fn material_grid() -> Scene {
+    let mut world = HittableList::new(Vec::new());
+
+    let checker = Arc::new(CheckerTexture::from_colors(1.0, Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8)));
+    world.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::from_texture(checker))));
+
+    let steps = [0.0, 0.25, 0.5, 0.75, 1.0];
+    for (column, &s) in steps.iter().enumerate() {
+        let x = 2.5 * (column as f64 - 2.0);
+        let row: [Arc<dyn Material>; 4] = [
+            Arc::new(Dielectric::new(1.1 + 0.8 * s)),
+            Arc::new(Dielectric::rough(1.5, 0.5 * s)),
+            Arc::new(Metal::aluminium(0.5 * s)),
+            Arc::new(Metal::gold(0.5 * s)),
+        ];
+        for (depth, mat) in row.into_iter().enumerate() {
+            world.add(sphere(Point3::new(x, 1.0, -2.5 * depth as f64), 1.0, mat));
+        }
+    }
+
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 800;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+    camera.vfov = 35.0;
+    camera.lookfrom = Point3::new(0.0, 9.0, 14.0);
+    camera.lookat = Point3::new(0.0, 0.0, -3.75);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.defocus_angle = 0.0;
+
+    Scene { world, camera }
+}
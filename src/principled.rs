@@ -0,0 +1,360 @@
+use crate::fresnel::{fr_dielectric, schlick, schlick_color};
+use crate::hittable::HitRecord;
+use crate::material::{Dielectric, Material};
+use crate::microfacet::{roughness_to_alpha, same_hemisphere, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::rtweekend::*;
+use crate::texture::{SolidColor, Texture};
+use std::sync::Arc;
+
+// Lower bound on the microfacet alpha of every lobe. Keeping the lobes slightly rough means they
+// all have a finite density, so the whole material can take part in light sampling and MIS.
+const MIN_ALPHA: f64 = 2e-3;
+// Normal incidence reflectance of the clearcoat layer (a polyurethane-like IOR of 1.5)
+const CLEARCOAT_F0: f64 = 0.04;
+
+// Disney-style "principled" uber material, combining a diffuse base with sheen, a GGX specular
+// lobe blended between dielectric and metal, a rough glass lobe and a clearcoat layer.
+//
+// Every parameter is a texture; scalar parameters read the first channel. Use the `with_*`
+// methods for constant values, or assign a texture to the field directly.
+//
+// Lobe weights are chosen so the material never reflects more than it receives: metallic and
+// transmission split the base between conductor, glass and the opaque dielectric, and the
+// clearcoat takes its Fresnel reflectance away from everything below it.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Dielectric reflectance at normal incidence, as a fraction of 8% (0.5 gives the usual 4%)
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: Arc<dyn Texture>,
+    // Stretches the specular highlight along the surface tangent, in [0,1]
+    pub anisotropic: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+}
+
+fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::scalar(value))
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn from_texture(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.03),
+            transmission: constant(0.0),
+            ior: constant(1.5),
+            anisotropic: constant(0.0),
+            emission: Arc::new(SolidColor::new(Color::new(0.0, 0.0, 0.0))),
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: f64) -> Self {
+        self.metallic = constant(metallic);
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = constant(roughness);
+        self
+    }
+
+    pub fn with_specular(mut self, specular: f64, tint: f64) -> Self {
+        self.specular = constant(specular);
+        self.specular_tint = constant(tint);
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: f64, tint: f64) -> Self {
+        self.sheen = constant(sheen);
+        self.sheen_tint = constant(tint);
+        self
+    }
+
+    pub fn with_clearcoat(mut self, clearcoat: f64, roughness: f64) -> Self {
+        self.clearcoat = constant(clearcoat);
+        self.clearcoat_roughness = constant(roughness);
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: f64, ior: f64) -> Self {
+        self.transmission = constant(transmission);
+        self.ior = constant(ior);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_anisotropic(mut self, anisotropic: f64) -> Self {
+        self.anisotropic = constant(anisotropic);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_emission(mut self, emission: Color) -> Self {
+        self.emission = Arc::new(SolidColor::new(emission));
+        self
+    }
+
+    // Evaluates the textures at the hit point and sets up the lobes as seen from `r_in`.
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord) -> PrincipledBsdf {
+        let scalar = |t: &Arc<dyn Texture>| t.value(rec.u, rec.v, &rec.p).x();
+        let base = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic).clamp(0.0, 1.0);
+        let roughness = scalar(&self.roughness).clamp(0.0, 1.0);
+        let transmission = scalar(&self.transmission).clamp(0.0, 1.0);
+        let clearcoat = scalar(&self.clearcoat).clamp(0.0, 1.0);
+        let ior = scalar(&self.ior).max(1.0);
+
+        // Hue and saturation of the base color, with unit luminance
+        let lum = luminance(base);
+        let tint = if lum > 0.0 { base / lum } else { Color::new(1.0, 1.0, 1.0) };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mix = |t: f64| (1.0 - t) * white + t * tint;
+
+        let specular_f0 = 0.08 * scalar(&self.specular).clamp(0.0, 1.0) * mix(scalar(&self.specular_tint).clamp(0.0, 1.0));
+        let sheen = scalar(&self.sheen).max(0.0) * mix(scalar(&self.sheen_tint).clamp(0.0, 1.0));
+
+        let aspect = (1.0 - 0.9 * scalar(&self.anisotropic).clamp(0.0, 1.0)).sqrt();
+        let alpha = roughness_to_alpha(roughness);
+        let specular = TrowbridgeReitz::new((alpha / aspect).max(MIN_ALPHA), (alpha * aspect).max(MIN_ALPHA));
+        let coat = TrowbridgeReitz::isotropic(roughness_to_alpha(scalar(&self.clearcoat_roughness)).max(MIN_ALPHA));
+
+        let frame = Onb::from_normal_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.local(&-r_in.direction().unit_vector());
+
+        let mut bsdf = PrincipledBsdf {
+            frame,
+            wo,
+            base,
+            metallic,
+            specular_f0,
+            sheen,
+            roughness,
+            dielectric_weight: (1.0 - metallic) * (1.0 - transmission),
+            glass_weight: (1.0 - metallic) * transmission,
+            clearcoat,
+            base_scale: 1.0 - clearcoat * schlick(wo.z(), CLEARCOAT_F0),
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            specular,
+            coat,
+            lobe_pdf: [0.0; 4],
+        };
+        bsdf.lobe_pdf = bsdf.lobe_probabilities();
+        bsdf
+    }
+}
+
+const LOBE_CLEARCOAT: usize = 0;
+const LOBE_SPECULAR: usize = 1;
+const LOBE_DIFFUSE: usize = 2;
+const LOBE_GLASS: usize = 3;
+
+// The principled lobes at one hit point, in the local shading frame where the normal is +z and
+// x follows the surface tangent.
+struct PrincipledBsdf {
+    frame: Onb,
+    wo: Vec3,
+    base: Color,
+    metallic: f64,
+    specular_f0: Color,
+    sheen: Color,
+    roughness: f64,
+    // Fractions of the base layer that are opaque dielectric and glass; the rest is metal
+    dielectric_weight: f64,
+    glass_weight: f64,
+    clearcoat: f64,
+    // Energy left for the base layer after the clearcoat's reflection
+    base_scale: f64,
+    eta: f64,
+    specular: TrowbridgeReitz,
+    coat: TrowbridgeReitz,
+    // Probability of sampling each lobe, roughly in proportion to its reflectance
+    lobe_pdf: [f64; 4],
+}
+
+impl PrincipledBsdf {
+    fn lobe_probabilities(&self) -> [f64; 4] {
+        let cos_o = self.wo.z().clamp(0.0, 1.0);
+        let dielectric_f = luminance(schlick_color(cos_o, self.specular_f0));
+        let mut weights = [0.0; 4];
+        weights[LOBE_CLEARCOAT] = self.clearcoat * schlick(cos_o, CLEARCOAT_F0);
+        weights[LOBE_SPECULAR] = self.base_scale
+            * (self.metallic * luminance(schlick_color(cos_o, self.base)).max(0.01) + self.dielectric_weight * dielectric_f);
+        weights[LOBE_DIFFUSE] =
+            self.base_scale * self.dielectric_weight * (1.0 - dielectric_f) * (luminance(self.base) + luminance(self.sheen)).max(0.01);
+        weights[LOBE_GLASS] = self.base_scale * self.glass_weight;
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        weights.map(|w| w / total)
+    }
+
+    // GGX reflection off `distribution` with Fresnel `fresnel`: value times cos(theta_i), and pdf
+    fn microfacet_reflection(distribution: &TrowbridgeReitz, wo: &Vec3, wi: &Vec3, fresnel: &dyn Fn(f64) -> Color) -> (Color, f64) {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if !same_hemisphere(wo, wi) || wo.z() <= 0.0 {
+            return (black, 0.0);
+        }
+        let wm = *wo + *wi;
+        if wm.near_zero() {
+            return (black, 0.0);
+        }
+        let wm = wm.unit_vector();
+        let f = distribution.d(&wm) * distribution.g(wo, wi) / (4.0 * wo.z());
+        let pdf = distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs());
+        (fresnel(wo.dot(&wm).abs()) * f, pdf)
+    }
+
+    // Burley's diffuse with retro-reflection, renormalized as in Frostbite so that together with
+    // the specular lobe it stays energy conserving, plus the sheen term.
+    fn diffuse(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if !same_hemisphere(wo, wi) || wo.z() <= 0.0 {
+            return (black, 0.0);
+        }
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let wh = (*wo + *wi).unit_vector();
+        let cos_d = wi.dot(&wh).clamp(0.0, 1.0);
+
+        let energy_bias = 0.5 * self.roughness;
+        let energy_factor = 1.0 + (1.0 / 1.51 - 1.0) * self.roughness;
+        let fd90 = energy_bias + 2.0 * cos_d * cos_d * self.roughness;
+        let fd = (1.0 + (fd90 - 1.0) * (1.0 - cos_o).powi(5)) * (1.0 + (fd90 - 1.0) * (1.0 - cos_i).powi(5)) * energy_factor;
+
+        // The light the dielectric specular lobe reflected no longer reaches the diffuse layer
+        let transmitted = 1.0 - luminance(schlick_color(cos_o, self.specular_f0));
+        let f = self.base * (fd / PI) + self.sheen * (1.0 - cos_d).powi(5);
+        (transmitted * f * cos_i, cos_i / PI)
+    }
+
+    fn glass_fresnel(&self, cos_theta: f64) -> Color {
+        let r = fr_dielectric(cos_theta, self.eta);
+        Color::new(r, r, r)
+    }
+
+    // Sum of all lobes and the combined sampling density for the local direction `wi`
+    fn eval(&self, wi: &Vec3) -> (Color, f64) {
+        let wo = &self.wo;
+        let mut f = Color::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+
+        if self.clearcoat > 0.0 {
+            let (cf, cpdf) = Self::microfacet_reflection(&self.coat, wo, wi, &|c| {
+                let r = schlick(c, CLEARCOAT_F0);
+                Color::new(r, r, r)
+            });
+            f += self.clearcoat * cf;
+            pdf += self.lobe_pdf[LOBE_CLEARCOAT] * cpdf;
+        }
+
+        let (sf, spdf) = Self::microfacet_reflection(&self.specular, wo, wi, &|c| {
+            self.metallic * schlick_color(c, self.base) + self.dielectric_weight * schlick_color(c, self.specular_f0)
+        });
+        f += self.base_scale * sf;
+        pdf += self.lobe_pdf[LOBE_SPECULAR] * spdf;
+
+        if self.dielectric_weight > 0.0 {
+            let (df, dpdf) = self.diffuse(wo, wi);
+            f += (self.base_scale * self.dielectric_weight) * df;
+            pdf += self.lobe_pdf[LOBE_DIFFUSE] * dpdf;
+        }
+
+        if self.glass_weight > 0.0 {
+            let (gf, gpdf) = Dielectric::microfacet_eval_pdf(&self.specular, wo, wi, self.eta, &|c| self.glass_fresnel(c));
+            // Only light passing through the surface takes on the base color
+            let tint = if same_hemisphere(wo, wi) { Color::new(1.0, 1.0, 1.0) } else { self.base };
+            f += (self.base_scale * self.glass_weight) * gf * tint;
+            pdf += self.lobe_pdf[LOBE_GLASS] * gpdf;
+        }
+
+        (f, pdf)
+    }
+
+    // Picks a lobe and samples a local direction from it
+    fn sample(&self, sampler: &mut Sampler) -> Option<Vec3> {
+        let wo = &self.wo;
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let u = sampler.next_f64();
+        let mut cdf = 0.0;
+        let mut lobe = LOBE_GLASS;
+        for (i, p) in self.lobe_pdf.iter().enumerate() {
+            cdf += p;
+            if u < cdf {
+                lobe = i;
+                break;
+            }
+        }
+        if self.lobe_pdf[lobe] <= 0.0 {
+            return None;
+        }
+
+        let reflect_off = |distribution: &TrowbridgeReitz, sampler: &mut Sampler| {
+            let wm = distribution.sample_wm(wo, sampler.next_f64(), sampler.next_f64());
+            let wi = Vec3::reflect(&-*wo, &wm);
+            if same_hemisphere(wo, &wi) { Some(wi) } else { None }
+        };
+        match lobe {
+            LOBE_CLEARCOAT => reflect_off(&self.coat, sampler),
+            LOBE_SPECULAR => reflect_off(&self.specular, sampler),
+            LOBE_DIFFUSE => Some(Vec3::random_cosine_direction(sampler)),
+            _ => Dielectric::microfacet_sample(&self.specular, wo, self.eta, &|c| self.glass_fresnel(c), sampler),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        let bsdf = self.bsdf(r_in, rec);
+        let wi = match bsdf.sample(sampler) {
+            Some(wi) => wi,
+            None => return false,
+        };
+        let (f, pdf) = bsdf.eval(&wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = f / pdf;
+        *scattered = Ray::new(rec.p, bsdf.frame.transform(&wi));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let bsdf = self.bsdf(r_in, rec);
+        bsdf.eval(&bsdf.frame.local(&wi.unit_vector())).0
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let bsdf = self.bsdf(r_in, rec);
+        bsdf.eval(&bsdf.frame.local(&wi.unit_vector())).1
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.emission.value(rec.u, rec.v, &rec.p)
+    }
+}
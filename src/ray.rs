@@ -4,12 +4,22 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    // Hero wavelength in nanometers when rendering spectrally, zero otherwise
+    wavelength: f64,
 }
 
 impl Ray {
 
     pub fn new(o: Point3, d: Vec3) -> Ray {
-        Ray { origin: o, direction: d }
+        Ray { origin: o, direction: d, wavelength: 0.0 }
+    }
+
+    pub fn with_wavelength(self, lambda: f64) -> Ray {
+        Ray { wavelength: lambda, ..self }
+    }
+
+    pub fn wavelength(&self) -> f64 {
+        self.wavelength
     }
 
     pub fn origin(&self) -> Point3 {
@@ -0,0 +1,62 @@
+use crate::rtweekend::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// SplitMix64's output function, which scrambles the bits of a 64-bit number
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Small, fast random number generator (SplitMix64). Its whole state is one number, so a new
+// stream can be started for every camera sample at no cost, and a render seeded the same way
+// comes out bit for bit the same however its pixels are shared among threads.
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    state: u64,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self { state: mix(seed) }
+    }
+
+    // Seeded from the OS, different every run
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    // Stream for sample `sample` of pixel (x, y) of a render seeded with `seed`
+    pub fn for_pixel_sample(seed: u64, x: i32, y: i32, sample: i32) -> Self {
+        let mut h = mix(seed);
+        for value in [x, y, sample] {
+            h = mix(h ^ value as u32 as u64);
+        }
+        Self { state: h }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    // Random real in [0,1), from the top 53 bits
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Random real in [min,max)
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    pub fn in_unit_disk(&mut self) -> Vec3 {
+        loop {
+            let p = Vec3::new(self.range(-1.0, 1.0), self.range(-1.0, 1.0), 0.0);
+            if p.length_squared() < 1.0 {
+                return p;
+            }
+        }
+    }
+}
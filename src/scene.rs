@@ -0,0 +1,1039 @@
+use crate::alpha_mask::AlphaMask;
+use crate::bump::Bumped;
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::environment::EnvironmentLight;
+use crate::faces::Faces;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::ies::IesProfile;
+use crate::instance::Instance;
+use crate::layered::{Coated, MixMaterial};
+use crate::light::{DiffuseAreaLight, DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::gltf;
+use crate::pbrt;
+use crate::ply;
+use crate::principled::Principled;
+use crate::quad::{make_box, Quad};
+use crate::rtweekend::*;
+use crate::sky::SunSky;
+use crate::sphere::Sphere;
+use crate::subsurface::Subsurface;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use crate::triangle::make_mesh;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Text description of a scene: camera, textures, materials, shapes, instances and lights.
+//
+// A file is a list of statements, one per line. Each statement is a keyword followed by
+// arguments, and may open a block in braces holding more statements:
+//
+//     # Comments run to the end of the line
+//     camera {
+//         lookfrom 13 2 3
+//         vfov 20
+//     }
+//     material lambertian ground {
+//         albedo 0.5 0.5 0.5
+//     }
+//     sphere {
+//         center 0 -1000 0
+//         radius 1000
+//         material ground
+//     }
+//
+// Arguments are numbers, bare words or "quoted strings". Names can be either kind of word, and
+// paths are relative to the scene file.
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+}
+
+// Problem in a scene file, at a 1-based line and column, or at line 0 for problems with the
+// file as a whole
+#[derive(Debug)]
+pub struct SceneError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}", self.message);
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+fn error<T>(line: usize, column: usize, message: String) -> Result<T> {
+    Err(SceneError { line, column, message })
+}
+
+impl Scene {
+    // Loads a scene file, or a pbrt or glTF scene going by the extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+        let path = path.as_ref();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match ext.as_str() {
+            "pbrt" => return pbrt::load(path),
+            "gltf" | "glb" => return gltf::load(path),
+            _ => {}
+        }
+        let text = fs::read_to_string(path)
+            .or_else(|e| error(0, 0, format!("can't read scene file {}: {}", path.display(), e)))?;
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Self::parse_in(&text, base_dir)
+    }
+
+    fn parse_in(text: &str, base_dir: PathBuf) -> Result<Scene> {
+        let statements = Parser::new(text)?.statements(None)?;
+        let mut builder = Builder {
+            base_dir,
+            camera: Camera::new(),
+            world: Vec::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            subsurface: HashMap::new(),
+            objects: HashMap::new(),
+            sampler: Sampler::new(0),
+        };
+        for statement in &statements {
+            builder.top_level(statement)?;
+        }
+        let world = HittableList::new(vec![Arc::new(BvhNode::new(builder.world))]);
+        Ok(Scene { world, camera: builder.camera })
+    }
+}
+
+// Tokens
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+    Newline,
+    End,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_column) = (line, column);
+        let push = |tokens: &mut Vec<Token>, kind| tokens.push(Token { kind, line: start_line, column: start_column });
+        match c {
+            '\n' => {
+                chars.next();
+                push(&mut tokens, TokenKind::Newline);
+                line += 1;
+                column = 1;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                column += 1;
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '{' | '}' => {
+                chars.next();
+                column += 1;
+                push(&mut tokens, if c == '{' { TokenKind::Open } else { TokenKind::Close });
+            }
+            '"' => {
+                chars.next();
+                column += 1;
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => {
+                            column += 1;
+                            break;
+                        }
+                        Some('\n') | None => return error(start_line, start_column, "unterminated string".to_string()),
+                        Some(c) => {
+                            column += 1;
+                            s.push(c);
+                        }
+                    }
+                }
+                push(&mut tokens, TokenKind::Str(s));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"' | '#') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                    column += 1;
+                }
+                let numeric = word.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'));
+                let kind = match word.parse::<f64>() {
+                    Ok(x) if numeric => TokenKind::Number(x),
+                    Err(_) if numeric => return error(start_line, start_column, format!("bad number '{}'", word)),
+                    _ => TokenKind::Word(word),
+                };
+                push(&mut tokens, kind);
+            }
+        }
+    }
+    tokens.push(Token { kind: TokenKind::End, line, column });
+    Ok(tokens)
+}
+
+// Syntax
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+struct Arg {
+    value: Value,
+    line: usize,
+    column: usize,
+}
+
+// One statement: `keyword args... [{ body }]`
+#[derive(Debug)]
+struct Statement {
+    keyword: String,
+    args: Vec<Arg>,
+    body: Option<Vec<Statement>>,
+    line: usize,
+    column: usize,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self> {
+        Ok(Self { tokens: tokenize(text)?, pos: 0 })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek().kind == TokenKind::Newline {
+            self.pos += 1;
+        }
+    }
+
+    // Statements up to the end of the file, or up to the closing brace of the block opened by
+    // the token `open`
+    fn statements(&mut self, open: Option<&Token>) -> Result<Vec<Statement>> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_newlines();
+            let token = self.peek().clone();
+            match (token.kind, open) {
+                (TokenKind::End, None) => return Ok(statements),
+                (TokenKind::End, Some(open)) => return error(open.line, open.column, "'{' is never closed".to_string()),
+                (TokenKind::Close, Some(_)) => {
+                    self.pos += 1;
+                    return Ok(statements);
+                }
+                (TokenKind::Close, None) => return error(token.line, token.column, "unexpected '}'".to_string()),
+                (TokenKind::Word(keyword), _) => {
+                    self.pos += 1;
+                    statements.push(self.statement(keyword, token.line, token.column)?);
+                }
+                _ => return error(token.line, token.column, "expected a keyword".to_string()),
+            }
+        }
+    }
+
+    fn statement(&mut self, keyword: String, line: usize, column: usize) -> Result<Statement> {
+        let mut args = Vec::new();
+        loop {
+            let token = self.peek().clone();
+            let value = match token.kind {
+                TokenKind::Number(x) => Value::Number(x),
+                TokenKind::Word(s) | TokenKind::Str(s) => Value::Text(s),
+                TokenKind::Open => {
+                    self.pos += 1;
+                    let body = self.statements(Some(&token))?;
+                    return Ok(Statement { keyword, args, body: Some(body), line, column });
+                }
+                _ => return Ok(Statement { keyword, args, body: None, line, column }),
+            };
+            args.push(Arg { value, line: token.line, column: token.column });
+            self.pos += 1;
+        }
+    }
+}
+
+impl Statement {
+    fn fail<T>(&self, message: String) -> Result<T> {
+        error(self.line, self.column, message)
+    }
+
+    fn unknown<T>(&self, context: &str) -> Result<T> {
+        self.fail(format!("unknown setting '{}' in {}", self.keyword, context))
+    }
+
+    fn body(&self) -> Result<&[Statement]> {
+        match &self.body {
+            Some(body) => Ok(body),
+            None => self.fail(format!("'{}' needs a {{ ... }} block", self.keyword)),
+        }
+    }
+
+    fn no_body(&self) -> Result<()> {
+        match self.body {
+            Some(_) => self.fail(format!("'{}' doesn't take a block", self.keyword)),
+            None => Ok(()),
+        }
+    }
+
+    fn arg_count(&self, counts: &[usize]) -> Result<()> {
+        if counts.contains(&self.args.len()) {
+            return Ok(());
+        }
+        let expected = counts.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(" or ");
+        self.fail(format!("'{}' takes {} values, found {}", self.keyword, expected, self.args.len()))
+    }
+
+    fn number_at(&self, i: usize) -> Result<f64> {
+        let arg = &self.args[i];
+        match &arg.value {
+            Value::Number(x) => Ok(*x),
+            Value::Text(s) => error(arg.line, arg.column, format!("expected a number, found '{}'", s)),
+        }
+    }
+
+    fn text_at(&self, i: usize) -> Result<&str> {
+        let arg = &self.args[i];
+        match &arg.value {
+            Value::Text(s) => Ok(s),
+            Value::Number(x) => error(arg.line, arg.column, format!("expected a name, found {}", x)),
+        }
+    }
+
+    fn numbers(&self, n: usize) -> Result<Vec<f64>> {
+        self.no_body()?;
+        self.arg_count(&[n])?;
+        (0..n).map(|i| self.number_at(i)).collect()
+    }
+
+    fn number(&self) -> Result<f64> {
+        Ok(self.numbers(1)?[0])
+    }
+
+    fn integer(&self) -> Result<i32> {
+        let x = self.number()?;
+        if x.fract() != 0.0 || x < 0.0 {
+            return self.fail(format!("'{}' must be a whole number", self.keyword));
+        }
+        Ok(x as i32)
+    }
+
+    fn positive_integer(&self) -> Result<i32> {
+        let n = self.integer()?;
+        if n < 1 {
+            return self.fail(format!("'{}' must be at least 1", self.keyword));
+        }
+        Ok(n)
+    }
+
+    fn vec3(&self) -> Result<Vec3> {
+        let v = self.numbers(3)?;
+        Ok(Vec3::new(v[0], v[1], v[2]))
+    }
+
+    // A color given as three numbers, or one number for a gray
+    fn color(&self) -> Result<Color> {
+        self.no_body()?;
+        self.arg_count(&[1, 3])?;
+        if self.args.len() == 1 {
+            let x = self.number_at(0)?;
+            return Ok(Color::new(x, x, x));
+        }
+        self.vec3()
+    }
+
+    fn text(&self) -> Result<&str> {
+        self.no_body()?;
+        self.arg_count(&[1])?;
+        self.text_at(0)
+    }
+
+    fn flag(&self) -> Result<()> {
+        self.no_body()?;
+        self.arg_count(&[0])
+    }
+}
+
+// Scene construction
+
+struct Builder {
+    base_dir: PathBuf,
+    camera: Camera,
+    world: Vec<Arc<dyn Hittable>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    // Subsurface materials by name, whose shapes need the scattering medium added inside
+    subsurface: HashMap<String, Arc<Subsurface>>,
+    objects: HashMap<String, Arc<dyn Hittable>>,
+    // Random numbers for noise textures, seeded the same way on every load
+    sampler: Sampler,
+}
+
+// Settings shared by every kind of shape
+#[derive(Default)]
+struct ShapeOptions {
+    material: Option<Arc<dyn Material>>,
+    subsurface: Option<Arc<Subsurface>>,
+    transform: Option<Transform>,
+    alpha: Option<Arc<dyn Texture>>,
+    cull_back: bool,
+    back_material: Option<Arc<dyn Material>>,
+    medium: Option<(Color, Color)>,
+    emission: Option<Color>,
+    two_sided: bool,
+}
+
+impl Builder {
+    fn path(&self, name: &str) -> PathBuf {
+        self.base_dir.join(name)
+    }
+
+    fn top_level(&mut self, s: &Statement) -> Result<()> {
+        match s.keyword.as_str() {
+            "camera" => self.camera(s),
+            "environment" => self.environment(s),
+            "texture" => self.texture(s),
+            "material" => self.material(s),
+            "light" => self.light(s),
+            "object" => self.object(s),
+            _ => {
+                let shape = self.shape(s, true)?;
+                self.world.push(shape);
+                Ok(())
+            }
+        }
+    }
+
+    fn camera(&mut self, s: &Statement) -> Result<()> {
+        s.arg_count(&[0])?;
+        let camera = &mut self.camera;
+        for p in s.body()? {
+            match p.keyword.as_str() {
+                "aspect_ratio" => {
+                    let ratio = match p.args.len() {
+                        2 => p.numbers(2)?,
+                        _ => vec![p.number()?, 1.0],
+                    };
+                    if !ratio.iter().all(|&x| x > 0.0 && x.is_finite()) {
+                        return p.fail("'aspect_ratio' must be positive".to_string());
+                    }
+                    camera.aspect_ratio = ratio[0] / ratio[1];
+                }
+                "image_width" => camera.image_width = p.positive_integer()?,
+                "samples_per_pixel" => camera.samples_per_pixel = p.positive_integer()?,
+                "max_depth" => camera.max_depth = p.positive_integer()?,
+                "rr_min_depth" => camera.rr_min_depth = p.integer()?,
+                "vfov" => camera.vfov = p.number()?,
+                "lookfrom" => camera.lookfrom = p.vec3()?,
+                "lookat" => camera.lookat = p.vec3()?,
+                "vup" => camera.vup = p.vec3()?,
+                "defocus_angle" => camera.defocus_angle = p.number()?,
+                "focus_dist" => camera.focus_dist = p.number()?,
+                "background" => camera.background = Some(p.color()?),
+                "spectral" => {
+                    p.flag()?;
+                    camera.spectral = true;
+                }
+                _ => return p.unknown("camera"),
+            }
+        }
+        Ok(())
+    }
+
+    fn environment(&mut self, s: &Statement) -> Result<()> {
+        s.arg_count(&[1])?;
+        let light: Arc<dyn Light> = match s.text_at(0)? {
+            "image" => {
+                let (mut file, mut rotation, mut intensity) = (None, 0.0, 1.0);
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "file" => file = Some((self.path(p.text()?), p)),
+                        "rotation" => rotation = p.number()?,
+                        "intensity" => intensity = p.number()?,
+                        _ => return p.unknown("environment image"),
+                    }
+                }
+                let Some((file, p)) = file else { return s.fail("environment image needs a file".to_string()) };
+                let env = EnvironmentLight::load(&file, rotation, intensity)
+                    .or_else(|e| p.fail(format!("can't load {}: {}", file.display(), e)))?;
+                Arc::new(env)
+            }
+            "sky" => {
+                // The sun is placed either by angle or by where and when on Earth, not both
+                let (mut elevation, mut azimuth, mut turbidity) = (45.0, 0.0, 3.0);
+                let (mut latitude, mut longitude, mut day, mut utc_hours) = (0.0, 0.0, 172, 12.0);
+                let (mut by_angle, mut by_location) = (None, None);
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "sun_elevation" | "sun_azimuth" => by_angle = Some(p),
+                        "latitude" | "longitude" | "day" | "utc_hours" => by_location = Some(p),
+                        _ => {}
+                    }
+                    if let (Some(_), Some(_)) = (by_angle, by_location) {
+                        return p.fail("give either sun_elevation/sun_azimuth or a location and time, not both".to_string());
+                    }
+                    match p.keyword.as_str() {
+                        "sun_elevation" => elevation = p.number()?,
+                        "sun_azimuth" => azimuth = p.number()?,
+                        "latitude" => latitude = p.number()?,
+                        "longitude" => longitude = p.number()?,
+                        "day" => {
+                            day = p.integer()?;
+                            if !(1..=366).contains(&day) {
+                                return p.fail("'day' must be between 1 and 366".to_string());
+                            }
+                        }
+                        "utc_hours" => utc_hours = p.number()?,
+                        "turbidity" => turbidity = p.number()?,
+                        _ => return p.unknown("environment sky"),
+                    }
+                }
+                if by_location.is_some() {
+                    Arc::new(SunSky::from_location(latitude, longitude, day as u32, utc_hours, turbidity))
+                } else {
+                    Arc::new(SunSky::from_elevation_azimuth(elevation, azimuth, turbidity))
+                }
+            }
+            other => return s.fail(format!("unknown environment type '{}'", other)),
+        };
+        self.camera.environment = Some(light);
+        Ok(())
+    }
+
+    // Name for a texture or material statement, `kind type name { ... }`
+    fn definition_name(&self, s: &Statement) -> Result<String> {
+        s.arg_count(&[2])?;
+        Ok(s.text_at(1)?.to_string())
+    }
+
+    fn lookup_texture(&self, p: &Statement, name: &str) -> Result<Arc<dyn Texture>> {
+        match self.textures.get(name) {
+            Some(texture) => Ok(texture.clone()),
+            None => p.fail(format!("no texture named '{}'", name)),
+        }
+    }
+
+    fn lookup_material(&self, p: &Statement) -> Result<Arc<dyn Material>> {
+        let name = p.text()?;
+        match self.materials.get(name) {
+            Some(mat) => Ok(mat.clone()),
+            None => p.fail(format!("no material named '{}'", name)),
+        }
+    }
+
+    // A texture given by name, or a constant color or gray value
+    fn texture_value(&self, p: &Statement) -> Result<Arc<dyn Texture>> {
+        if p.args.len() == 1 {
+            if let Value::Text(name) = &p.args[0].value {
+                p.no_body()?;
+                return self.lookup_texture(p, name);
+            }
+        }
+        Ok(Arc::new(SolidColor::new(p.color()?)))
+    }
+
+    fn texture(&mut self, s: &Statement) -> Result<()> {
+        let name = self.definition_name(s)?;
+        let texture: Arc<dyn Texture> = match s.text_at(0)? {
+            "solid" => {
+                let mut color = Color::new(0.5, 0.5, 0.5);
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "color" => color = p.color()?,
+                        _ => return p.unknown("solid texture"),
+                    }
+                }
+                Arc::new(SolidColor::new(color))
+            }
+            "checker" => {
+                let mut scale = 1.0;
+                let mut even: Arc<dyn Texture> = Arc::new(SolidColor::scalar(0.0));
+                let mut odd: Arc<dyn Texture> = Arc::new(SolidColor::scalar(1.0));
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "scale" => scale = p.number()?,
+                        "even" => even = self.texture_value(p)?,
+                        "odd" => odd = self.texture_value(p)?,
+                        _ => return p.unknown("checker texture"),
+                    }
+                }
+                Arc::new(CheckerTexture::new(scale, even, odd))
+            }
+            "noise" => {
+                let mut scale = 1.0;
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "scale" => scale = p.number()?,
+                        _ => return p.unknown("noise texture"),
+                    }
+                }
+                Arc::new(NoiseTexture::new(scale, &mut self.sampler))
+            }
+            "image" => {
+                let mut image = None;
+                for p in s.body()? {
+                    match p.keyword.as_str() {
+                        "file" => {
+                            let file = self.path(p.text()?);
+                            let texture = ImageTexture::load(&file)
+                                .or_else(|e| p.fail(format!("can't load {}: {}", file.display(), e)))?;
+                            image = Some(texture);
+                        }
+                        _ => return p.unknown("image texture"),
+                    }
+                }
+                match image {
+                    Some(image) => Arc::new(image),
+                    None => return s.fail("image texture needs a file".to_string()),
+                }
+            }
+            other => return s.fail(format!("unknown texture type '{}'", other)),
+        };
+        self.textures.insert(name, texture);
+        Ok(())
+    }
+
+    fn material(&mut self, s: &Statement) -> Result<()> {
+        let name = self.definition_name(s)?;
+        let kind = s.text_at(0)?;
+        let body = s.body()?;
+        let context = format!("{} material", kind);
+
+        let mat: Arc<dyn Material> = match kind {
+            "lambertian" => {
+                let mut albedo: Arc<dyn Texture> = Arc::new(SolidColor::scalar(0.5));
+                for p in body {
+                    match p.keyword.as_str() {
+                        "albedo" => albedo = self.texture_value(p)?,
+                        _ => return p.unknown(&context),
+                    }
+                }
+                Arc::new(Lambertian::from_texture(albedo))
+            }
+            "metal" | "conductor" => {
+                let (mut albedo, mut roughness) = (Color::new(0.8, 0.8, 0.8), 0.0);
+                let (mut preset, mut eta, mut k) = (None, None, None);
+                let mut thin_film = None;
+                for p in body {
+                    match (kind, p.keyword.as_str()) {
+                        ("metal", "albedo") => albedo = p.color()?,
+                        ("metal", "fuzz") | ("conductor", "roughness") => roughness = p.number()?,
+                        ("conductor", "preset") => preset = Some(p),
+                        ("conductor", "eta") => eta = Some(p.color()?),
+                        ("conductor", "k") => k = Some(p.color()?),
+                        (_, "thin_film") => thin_film = Some(p.numbers(2)?),
+                        _ => return p.unknown(&context),
+                    }
+                }
+                let mut mat = match (kind, preset, eta, k) {
+                    ("metal", ..) => Metal::new(albedo, roughness),
+                    (_, Some(p), None, None) => match p.text()? {
+                        "gold" => Metal::gold(roughness),
+                        "copper" => Metal::copper(roughness),
+                        "aluminium" | "aluminum" => Metal::aluminium(roughness),
+                        "silver" => Metal::silver(roughness),
+                        other => return p.fail(format!("unknown conductor preset '{}'", other)),
+                    },
+                    (_, None, Some(eta), Some(k)) => Metal::conductor(eta, k, roughness),
+                    _ => return s.fail("conductor needs either a preset or both eta and k".to_string()),
+                };
+                if let Some(film) = thin_film {
+                    mat = mat.with_thin_film(film[0], film[1]);
+                }
+                Arc::new(mat)
+            }
+            "dielectric" => {
+                let (mut ior, mut roughness) = (1.5, 0.0);
+                let mut preset = None;
+                let mut extras: Vec<&Statement> = Vec::new();
+                for p in body {
+                    match p.keyword.as_str() {
+                        "ior" => ior = p.number()?,
+                        "roughness" => roughness = p.number()?,
+                        "preset" => preset = Some(p),
+                        "cauchy" | "absorption" | "transmission_color" | "thin_film" => extras.push(p),
+                        _ => return p.unknown(&context),
+                    }
+                }
+                let mut mat = match preset {
+                    Some(p) => match p.text()? {
+                        "bk7" => Dielectric::bk7(),
+                        "diamond" => Dielectric::diamond(),
+                        other => return p.fail(format!("unknown dielectric preset '{}'", other)),
+                    },
+                    None => Dielectric::rough(ior, roughness),
+                };
+                for p in extras {
+                    mat = match p.keyword.as_str() {
+                        "cauchy" => p.numbers(2).map(|c| mat.with_cauchy(c[0], c[1]))?,
+                        "absorption" => mat.with_absorption(p.color()?),
+                        "transmission_color" => {
+                            p.arg_count(&[4])?;
+                            let color = Color::new(p.number_at(0)?, p.number_at(1)?, p.number_at(2)?);
+                            mat.with_transmission_color(color, p.number_at(3)?)
+                        }
+                        _ => p.numbers(2).map(|f| mat.with_thin_film(f[0], f[1]))?,
+                    };
+                }
+                Arc::new(mat)
+            }
+            "principled" => {
+                let mut mat = Principled::new(Color::new(0.8, 0.8, 0.8));
+                for p in body {
+                    match p.keyword.as_str() {
+                        "base_color" => mat.base_color = self.texture_value(p)?,
+                        "metallic" => mat.metallic = self.texture_value(p)?,
+                        "roughness" => mat.roughness = self.texture_value(p)?,
+                        "specular" => mat = p.numbers(2).map(|v| mat.with_specular(v[0], v[1]))?,
+                        "sheen" => mat = p.numbers(2).map(|v| mat.with_sheen(v[0], v[1]))?,
+                        "clearcoat" => mat = p.numbers(2).map(|v| mat.with_clearcoat(v[0], v[1]))?,
+                        "transmission" => mat = p.numbers(2).map(|v| mat.with_transmission(v[0], v[1]))?,
+                        "anisotropic" => mat.anisotropic = self.texture_value(p)?,
+                        "emission" => mat.emission = self.texture_value(p)?,
+                        _ => return p.unknown(&context),
+                    }
+                }
+                Arc::new(mat)
+            }
+            "mix" => {
+                let (mut a, mut b, mut amount) = (None, None, None);
+                for p in body {
+                    match p.keyword.as_str() {
+                        "a" => a = Some(self.lookup_material(p)?),
+                        "b" => b = Some(self.lookup_material(p)?),
+                        "amount" => amount = Some(self.texture_value(p)?),
+                        _ => return p.unknown(&context),
+                    }
+                }
+                let (Some(a), Some(b)) = (a, b) else { return s.fail("mix needs materials a and b".to_string()) };
+                match amount {
+                    Some(amount) => Arc::new(MixMaterial::with_mask(a, b, amount)),
+                    None => Arc::new(MixMaterial::new(a, b, 0.5)),
+                }
+            }
+            "coated" => {
+                let (mut base, mut ior, mut roughness, mut tint) = (None, 1.5, 0.0, Color::new(1.0, 1.0, 1.0));
+                for p in body {
+                    match p.keyword.as_str() {
+                        "base" => base = Some(self.lookup_material(p)?),
+                        "ior" => ior = p.number()?,
+                        "roughness" => roughness = p.number()?,
+                        "tint" => tint = p.color()?,
+                        _ => return p.unknown(&context),
+                    }
+                }
+                let Some(base) = base else { return s.fail("coated needs a base material".to_string()) };
+                Arc::new(Coated::new(base, ior, roughness).with_tint(tint))
+            }
+            "bump" | "normal_map" => {
+                let (mut base, mut map, mut scale) = (None, None, 1.0);
+                for p in body {
+                    match (kind, p.keyword.as_str()) {
+                        (_, "base") => base = Some(self.lookup_material(p)?),
+                        ("bump", "height") | ("normal_map", "normals") => map = Some(self.texture_value(p)?),
+                        ("bump", "scale") => scale = p.number()?,
+                        _ => return p.unknown(&context),
+                    }
+                }
+                let (Some(base), Some(map)) = (base, map) else {
+                    return s.fail(format!("{} needs a base material and a texture", kind));
+                };
+                if kind == "bump" {
+                    Arc::new(Bumped::bump_map(base, map, scale))
+                } else {
+                    Arc::new(Bumped::normal_map(base, map))
+                }
+            }
+            "subsurface" => {
+                let mut mat = Subsurface::new(Color::new(0.9, 0.9, 0.9), Color::new(0.5, 0.5, 0.5), 1.5);
+                let (mut ior, mut roughness) = (None, None);
+                for p in body {
+                    match p.keyword.as_str() {
+                        "preset" => {
+                            mat = match p.text()? {
+                                "marble" => Subsurface::marble(),
+                                "skin" => Subsurface::skin(),
+                                other => return p.fail(format!("unknown subsurface preset '{}'", other)),
+                            }
+                        }
+                        "albedo" => mat.albedo = p.color()?,
+                        "mean_free_path" => mat.mean_free_path = p.color()?,
+                        "ior" => ior = Some(p.number()?),
+                        "roughness" => roughness = Some(p.number()?),
+                        _ => return p.unknown(&context),
+                    }
+                }
+                if ior.is_some() || roughness.is_some() {
+                    mat = Subsurface::rough(mat.albedo, mat.mean_free_path, ior.unwrap_or(1.5), roughness.unwrap_or(0.0));
+                }
+                let mat = Arc::new(mat);
+                self.subsurface.insert(name.clone(), mat.clone());
+                mat
+            }
+            other => return s.fail(format!("unknown material type '{}'", other)),
+        };
+        self.materials.insert(name, mat);
+        Ok(())
+    }
+
+    fn light(&mut self, s: &Statement) -> Result<()> {
+        s.arg_count(&[1])?;
+        let kind = s.text_at(0)?;
+        let context = format!("{} light", kind);
+        let mut position = Point3::new(0.0, 0.0, 0.0);
+        let mut direction = Vec3::new(0.0, -1.0, 0.0);
+        let mut intensity = Color::new(1.0, 1.0, 1.0);
+        let (mut total_width, mut falloff_start) = (30.0, 20.0);
+        let mut profile = None;
+
+        for p in s.body()? {
+            match (kind, p.keyword.as_str()) {
+                ("point" | "spot", "position") => position = p.vec3()?,
+                ("spot" | "directional", "direction") | ("point", "nadir") => direction = p.vec3()?,
+                ("point" | "spot", "intensity") | ("directional", "irradiance") => intensity = p.color()?,
+                ("spot", "total_width") => total_width = p.number()?,
+                ("spot", "falloff_start") => falloff_start = p.number()?,
+                ("point" | "spot", "ies") => {
+                    let file = self.path(p.text()?);
+                    let ies = IesProfile::load(&file).or_else(|e| p.fail(format!("can't load {}: {}", file.display(), e)))?;
+                    profile = Some(Arc::new(ies));
+                }
+                ("point" | "spot" | "directional", _) => return p.unknown(&context),
+                _ => return s.fail(format!("unknown light type '{}'", kind)),
+            }
+        }
+
+        let light: Arc<dyn Light> = match kind {
+            "point" => {
+                let light = PointLight::new(position, intensity);
+                Arc::new(match profile {
+                    Some(profile) => light.with_ies_profile(profile, direction),
+                    None => light,
+                })
+            }
+            "spot" => {
+                let light = SpotLight::new(position, direction, intensity, total_width, falloff_start);
+                Arc::new(match profile {
+                    Some(profile) => light.with_ies_profile(profile),
+                    None => light,
+                })
+            }
+            "directional" => Arc::new(DirectionalLight::new(direction, intensity)),
+            other => return s.fail(format!("unknown light type '{}'", other)),
+        };
+        self.camera.lights.push(light);
+        Ok(())
+    }
+
+    // `object name { shapes... }` defines a group that is only placed by instances
+    fn object(&mut self, s: &Statement) -> Result<()> {
+        s.arg_count(&[1])?;
+        let name = s.text_at(0)?.to_string();
+        let mut group = Vec::new();
+        for child in s.body()? {
+            group.push(self.shape(child, false)?);
+        }
+        self.objects.insert(name, Arc::new(BvhNode::new(group)));
+        Ok(())
+    }
+
+    // Applies a transform setting on top of `transform`, in the order they are written
+    fn transform_setting(p: &Statement, transform: Option<Transform>) -> Result<Transform> {
+        let step = match p.keyword.as_str() {
+            "translate" => Transform::translate(p.vec3()?),
+            "scale" => {
+                p.no_body()?;
+                p.arg_count(&[1, 3])?;
+                if p.args.len() == 1 {
+                    let s = p.number_at(0)?;
+                    Transform::scale(s, s, s)
+                } else {
+                    let s = p.vec3()?;
+                    Transform::scale(s.x(), s.y(), s.z())
+                }
+            }
+            "rotate" => {
+                let r = p.numbers(4)?;
+                Transform::rotate(r[0], Vec3::new(r[1], r[2], r[3]))
+            }
+            _ => return p.unknown("transform"),
+        };
+        Ok(transform.map_or(step, |t| t.then(&step)))
+    }
+
+    fn shape(&mut self, s: &Statement, top_level: bool) -> Result<Arc<dyn Hittable>> {
+        let kind = s.keyword.as_str();
+        let context = kind.to_string();
+        let mut options = ShapeOptions::default();
+        let (mut center, mut radius) = (Point3::new(0.0, 0.0, 0.0), 1.0);
+        let (mut q, mut u, mut v) = (Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let (mut min, mut max) = (Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let (mut density, mut medium_albedo) = (None, Color::new(1.0, 1.0, 1.0));
+        let mut mesh = None;
+        let (mut emission_at, mut two_sided_at) = (None, None);
+
+        match kind {
+            "sphere" | "quad" | "box" | "mesh" => s.arg_count(&[0])?,
+            "instance" => s.arg_count(&[1])?,
+            _ => return s.fail(format!("unknown statement '{}'", kind)),
+        }
+        // An instance can leave its object as it is
+        let body = if kind == "instance" && s.body.is_none() { &[] } else { s.body()? };
+
+        for p in body {
+            match (kind, p.keyword.as_str()) {
+                ("sphere", "center") => center = p.vec3()?,
+                ("sphere", "radius") => radius = p.number()?,
+                ("quad", "q") => q = p.vec3()?,
+                ("quad", "u") => u = p.vec3()?,
+                ("quad", "v") => v = p.vec3()?,
+                ("box", "min") => min = p.vec3()?,
+                ("box", "max") => max = p.vec3()?,
+                ("mesh", "file") => {
+                    let file = self.path(p.text()?);
+                    let loaded = ply::load(&file).or_else(|e| p.fail(format!("can't load {}: {}", file.display(), e)))?;
+                    mesh = Some(Arc::new(loaded));
+                }
+                (_, "material") => {
+                    options.material = Some(self.lookup_material(p)?);
+                    options.subsurface = self.subsurface.get(p.text()?).cloned();
+                }
+                (_, "translate" | "scale" | "rotate") => options.transform = Some(Self::transform_setting(p, options.transform)?),
+                (_, "alpha") => options.alpha = Some(self.texture_value(p)?),
+                (_, "cull_back") => {
+                    p.flag()?;
+                    options.cull_back = true;
+                }
+                (_, "back_material") => options.back_material = Some(self.lookup_material(p)?),
+                (_, "density") => density = Some(p.color()?),
+                (_, "medium_albedo") => medium_albedo = p.color()?,
+                (_, "emission") if top_level => {
+                    options.emission = Some(p.color()?);
+                    emission_at = Some(p);
+                }
+                (_, "emission") => return p.fail("emission inside an object can't be sampled; set it on the instance".to_string()),
+                (_, "two_sided") => {
+                    p.flag()?;
+                    options.two_sided = true;
+                    two_sided_at = Some(p);
+                }
+                _ => return p.unknown(&context),
+            }
+        }
+        // Lights are sampled by area, which uneven scaling would get wrong
+        if let (Some(p), Some(transform)) = (emission_at, options.transform) {
+            if !transform.is_similarity() {
+                return p.fail("emission needs a shape scaled the same along every axis".to_string());
+            }
+        }
+        if let (Some(p), None) = (two_sided_at, emission_at) {
+            return p.fail("two_sided only applies to shapes with emission".to_string());
+        }
+        options.medium = density.map(|d| (d, medium_albedo));
+
+        let mat = options.material.clone();
+        let shape: Arc<dyn Hittable> = match kind {
+            "sphere" => Arc::new(Sphere::new(center, radius, mat)),
+            "quad" => Arc::new(Quad::new(q, u, v, mat)),
+            "box" => Arc::new(make_box(min, max, mat)),
+            "mesh" => match mesh {
+                // Vertex colors stand in for a material when there is none
+                Some(mesh) if mat.is_none() && !mesh.colors.is_empty() => {
+                    Arc::new(make_mesh(mesh, Some(Arc::new(Lambertian::from_vertex_colors()))))
+                }
+                Some(mesh) => Arc::new(make_mesh(mesh, mat)),
+                None => return s.fail("mesh needs a file".to_string()),
+            },
+            _ => {
+                let name = s.text_at(0)?;
+                match self.objects.get(name) {
+                    Some(object) => object.clone(),
+                    None => return s.fail(format!("no object named '{}'", name)),
+                }
+            }
+        };
+        Ok(self.finish_shape(shape, options))
+    }
+
+    // Wraps a shape with everything its options ask for, innermost first
+    fn finish_shape(&mut self, mut shape: Arc<dyn Hittable>, options: ShapeOptions) -> Arc<dyn Hittable> {
+        if let Some(transform) = options.transform {
+            shape = Arc::new(Instance::new(shape, transform));
+        }
+        if let Some(alpha) = options.alpha {
+            shape = Arc::new(AlphaMask::new(shape, alpha));
+        }
+        if options.cull_back || options.back_material.is_some() {
+            let mut faces = Faces::new(shape);
+            if options.cull_back {
+                faces = faces.with_back_face_culling();
+            }
+            if let Some(back) = options.back_material {
+                faces = faces.with_back_material(back);
+            }
+            shape = Arc::new(faces);
+        }
+        if let Some(subsurface) = options.subsurface {
+            shape = subsurface.fill(shape);
+        }
+        if let Some((density, albedo)) = options.medium {
+            shape = Arc::new(ConstantMedium::chromatic(shape, density, albedo));
+        }
+        if let Some(emit) = options.emission {
+            let light = if options.two_sided {
+                DiffuseAreaLight::two_sided(shape, emit)
+            } else {
+                DiffuseAreaLight::new(shape, emit)
+            };
+            self.camera.lights.push(light.clone());
+            shape = light;
+        }
+        shape
+    }
+}
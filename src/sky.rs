@@ -0,0 +1,210 @@
+use crate::hittable::HitRecord;
+use crate::light::{Light, LightSample};
+use crate::onb::Onb;
+use crate::rtweekend::*;
+
+// Angular radius of the sun disk as seen from the earth, in degrees
+const SUN_ANGULAR_RADIUS: f64 = 0.2665;
+// Maps the Preetham luminance (kcd/m^2) onto the renderer's working range
+const SKY_SCALE: f64 = 0.05;
+// Irradiance the unattenuated sun disk delivers at normal incidence, in the same units
+const SUN_IRRADIANCE: f64 = 8.0;
+
+// Preetham et al. analytic daylight sky with a matching sun disk.
+//
+// +y is up, north is -z and east is +x. Azimuth is measured from north towards east.
+pub struct SunSky {
+    #[allow(dead_code)]
+    pub turbidity: f64,
+    pub sky_intensity: f64,
+    pub sun_intensity: f64,
+    sun_direction: Vec3,
+    cos_sun_radius: f64,
+    zenith: [f64; 3],
+    perez: [[f64; 5]; 3],
+    sun_radiance: Color,
+}
+
+// Unit direction for an elevation above the horizon and azimuth, both in degrees.
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let (sin_el, cos_el) = degrees_to_radians(elevation).sin_cos();
+    let (sin_az, cos_az) = degrees_to_radians(azimuth).sin_cos();
+    Vec3::new(sin_az * cos_el, sin_el, -cos_az * cos_el)
+}
+
+// Approximate solar (elevation, azimuth) in degrees for a location and a UTC time of day,
+// following the NOAA general solar position equations.
+pub fn solar_position(latitude: f64, longitude: f64, day_of_year: u32, utc_hours: f64) -> (f64, f64) {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year as f64 - 1.0 + (utc_hours - 12.0) / 24.0);
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos() - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos() + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos() + 0.00148 * (3.0 * gamma).sin();
+
+    let true_solar_minutes = utc_hours * 60.0 + eqtime + 4.0 * longitude;
+    let hour_angle = degrees_to_radians(true_solar_minutes / 4.0 - 180.0);
+    let lat = degrees_to_radians(latitude);
+
+    let cos_zenith = (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos()).clamp(-1.0, 1.0);
+    let elevation = 90.0 - cos_zenith.acos().to_degrees();
+    let azimuth = hour_angle.sin().atan2(hour_angle.cos() * lat.sin() - decl.tan() * lat.cos()).to_degrees() + 180.0;
+    (elevation, azimuth.rem_euclid(360.0))
+}
+
+impl SunSky {
+    pub fn new(sun_direction: Vec3, turbidity: f64) -> Self {
+        let t = turbidity.clamp(1.7, 10.0);
+        let sun_direction = sun_direction.unit_vector();
+        let theta_s = sun_direction.y().clamp(-1.0, 1.0).acos().min(PI / 2.0);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let (t2, th2, th3) = (t * t, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = (0.00166 * th3 - 0.00375 * th2 + 0.00209 * theta_s) * t2
+            + (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * theta_s + 0.00394) * t
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * theta_s + 0.25886);
+        let zenith_yc = (0.00275 * th3 - 0.00610 * th2 + 0.00317 * theta_s) * t2
+            + (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * theta_s + 0.00516) * t
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * theta_s + 0.26688);
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let cos_sun_radius = degrees_to_radians(SUN_ANGULAR_RADIUS).cos();
+        let sun_solid_angle = 2.0 * PI * (1.0 - cos_sun_radius);
+
+        // Rayleigh and aerosol transmittance along the optical air mass, at nominal R, G and B
+        // wavelengths (micrometers), from the Preetham paper's appendix
+        let zenith_deg = theta_s.to_degrees();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - zenith_deg).powf(-1.253));
+        let beta = 0.04608365 * t - 0.04586025;
+        let transmittance = |lambda: f64| -> f64 {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        let sun_radiance = if sun_direction.y() > 0.0 {
+            Color::new(transmittance(0.680), transmittance(0.550), transmittance(0.440)) * (SUN_IRRADIANCE / sun_solid_angle)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        Self {
+            turbidity: t,
+            sky_intensity: 1.0,
+            sun_intensity: 1.0,
+            sun_direction,
+            cos_sun_radius,
+            zenith: [zenith_y, zenith_x, zenith_yc],
+            perez,
+            sun_radiance,
+        }
+    }
+
+    pub fn from_elevation_azimuth(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        Self::new(sun_direction(elevation, azimuth), turbidity)
+    }
+
+    pub fn from_location(latitude: f64, longitude: f64, day_of_year: u32, utc_hours: f64, turbidity: f64) -> Self {
+        let (elevation, azimuth) = solar_position(latitude, longitude, day_of_year, utc_hours);
+        Self::from_elevation_azimuth(elevation, azimuth, turbidity)
+    }
+
+    fn perez_f(coeffs: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coeffs;
+        (1.0 + a * (b / cos_theta.max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+
+    // The model ends at the horizon. Below it the horizon's radiance carries on, so a ground
+    // that stops short of the horizon, like a large sphere, doesn't leave a black band.
+    pub fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let d = direction.unit_vector();
+        let cos_theta = d.y().max(0.0);
+
+        let theta_s = self.sun_direction.y().clamp(-1.0, 1.0).acos().min(PI / 2.0);
+        let gamma = d.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let mut values = [0.0; 3];
+        for (i, value) in values.iter_mut().enumerate() {
+            let num = Self::perez_f(&self.perez[i], cos_theta, gamma);
+            let den = Self::perez_f(&self.perez[i], 1.0, theta_s);
+            *value = self.zenith[i] * num / den;
+        }
+
+        let [lum, x, y] = values;
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let lum = lum * SKY_SCALE * self.sky_intensity;
+        let rgb = xyz_to_rgb(Color::new(x / y * lum, lum, (1.0 - x - y) / y * lum));
+        Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+
+    fn in_sun_disk(&self, d: &Vec3) -> bool {
+        d.unit_vector().dot(&self.sun_direction) >= self.cos_sun_radius
+    }
+
+    fn sun_select_probability(&self) -> f64 {
+        if self.sun_direction.y() > 0.0 && self.sun_intensity > 0.0 { 0.5 } else { 0.0 }
+    }
+
+    fn sun_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+impl Light for SunSky {
+    // Samples the sun disk uniformly by solid angle, or the upper hemisphere uniformly for the sky.
+    fn sample_li(&self, rec: &HitRecord, sampler: &mut Sampler) -> Option<LightSample> {
+        let wi = if sampler.next_f64() < self.sun_select_probability() {
+            let cos_theta = 1.0 - sampler.next_f64() * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * sampler.next_f64();
+            Onb::new(&self.sun_direction).transform(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta))
+        } else {
+            let cos_theta = sampler.next_f64();
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * sampler.next_f64();
+            Vec3::new(phi.cos() * sin_theta, cos_theta, phi.sin() * sin_theta)
+        };
+
+        let pdf = self.pdf_li(rec, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: self.le(&Ray::new(rec.p, wi)),
+            pdf,
+            distance: INFINITY,
+            is_delta: false,
+        })
+    }
+
+    fn pdf_li(&self, _rec: &HitRecord, wi: &Vec3) -> f64 {
+        let p_sun = self.sun_select_probability();
+        let mut pdf = 0.0;
+        if wi.y() > 0.0 {
+            pdf += (1.0 - p_sun) / (2.0 * PI);
+        }
+        if self.in_sun_disk(wi) {
+            pdf += p_sun * self.sun_pdf();
+        }
+        pdf
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        let d = r.direction();
+        let mut radiance = self.sky_radiance(&d);
+        if self.in_sun_disk(&d) {
+            radiance += self.sun_intensity * self.sun_radiance;
+        }
+        radiance
+    }
+}
@@ -0,0 +1,275 @@
+use crate::rtweekend::*;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul};
+use std::sync::OnceLock;
+
+// Number of wavelengths carried by each spectral path (one hero plus its rotations)
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// Quantity carried along a path by the integrator: RGB Color, or a SampledSpectrum in spectral
+// mode.
+pub trait PathSpectrum:
+    Copy + Add<Output = Self> + AddAssign + Mul<Output = Self> + Mul<f64, Output = Self> + Div<f64, Output = Self> + DivAssign<f64>
+{
+    fn splat(v: f64) -> Self;
+    fn max_component(&self) -> f64;
+}
+
+impl PathSpectrum for Color {
+    fn splat(v: f64) -> Self {
+        Color::new(v, v, v)
+    }
+
+    fn max_component(&self) -> f64 {
+        Vec3::max_component(self)
+    }
+}
+
+// Spectral values at the wavelengths of a SampledWavelengths
+#[derive(Copy, Clone, Debug)]
+pub struct SampledSpectrum {
+    values: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    // Upsamples an RGB value to a smooth spectrum (Smits 1999) and evaluates it at `lambda`.
+    pub fn from_rgb(rgb: Color, lambda: &SampledWavelengths) -> Self {
+        let mut values = [0.0; N_SPECTRUM_SAMPLES];
+        for (v, &l) in values.iter_mut().zip(lambda.lambda.iter()) {
+            *v = rgb_to_spectrum(rgb, l);
+        }
+        Self { values }
+    }
+}
+
+impl PathSpectrum for SampledSpectrum {
+    fn splat(v: f64) -> Self {
+        Self { values: [v; N_SPECTRUM_SAMPLES] }
+    }
+
+    fn max_component(&self) -> f64 {
+        self.values.iter().fold(f64::NEG_INFINITY, |m, &v| m.max(v))
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+        for (v, o) in values.iter_mut().zip(other.values.iter()) {
+            *v += o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: SampledSpectrum) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+        for (v, o) in values.iter_mut().zip(other.values.iter()) {
+            *v *= o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, rhs: f64) -> SampledSpectrum {
+        SampledSpectrum { values: self.values.map(|v| v * rhs) }
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn div(self, rhs: f64) -> SampledSpectrum {
+        SampledSpectrum { values: self.values.map(|v| v / rhs) }
+    }
+}
+
+impl DivAssign<f64> for SampledSpectrum {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs;
+    }
+}
+
+// Hero wavelength sampling: one wavelength is importance sampled over the visible range and the
+// others are evenly spaced rotations of the same random number.
+#[derive(Copy, Clone, Debug)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; N_SPECTRUM_SAMPLES],
+    pub pdf: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; N_SPECTRUM_SAMPLES];
+        for i in 0..N_SPECTRUM_SAMPLES {
+            let up = (u + i as f64 / N_SPECTRUM_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Keeps only the hero wavelength, after something wavelength dependent such as dispersion
+    // made the path valid for that wavelength alone.
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1..].iter().all(|&p| p == 0.0) {
+            return;
+        }
+        for p in self.pdf[1..].iter_mut() {
+            *p = 0.0;
+        }
+        self.pdf[0] /= N_SPECTRUM_SAMPLES as f64;
+    }
+
+    // Monte Carlo estimate of the linear sRGB color of spectral radiance `l`.
+    pub fn to_rgb(self, l: &SampledSpectrum) -> Color {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            xyz += cie_xyz(self.lambda[i]) * (l.values[i] / self.pdf[i]);
+        }
+        let constants = spectral_constants();
+        let xyz = xyz / (N_SPECTRUM_SAMPLES as f64 * constants.cie_y_integral);
+        let rgb = xyz_to_rgb(xyz);
+        let white = constants.white_rgb;
+        Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+    }
+}
+
+// Importance sampling of visible wavelengths (Radziszewski et al. 2009, as used by pbrt-v4)
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let c = (0.0072 * (lambda - 538.0)).cosh();
+    0.0039398042 / (c * c)
+}
+
+// CIE 1931 2-degree color matching functions, multi-lobe Gaussian fit by Wyman, Sloan and Shirley
+pub fn cie_xyz(lambda: f64) -> Color {
+    let g = |mu: f64, s1: f64, s2: f64| -> f64 {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        (-0.5 * t * t).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// Linear sRGB of a reflectance spectrum lit by the equal-energy illuminant, white balanced like
+// SampledWavelengths::to_rgb so that a constant reflectance of one gives (1,1,1). Used to
+// render wavelength dependent effects in RGB mode.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let mut xyz = Color::new(0.0, 0.0, 0.0);
+    let mut white = Color::new(0.0, 0.0, 0.0);
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        let cmf = cie_xyz(lambda);
+        xyz += cmf * reflectance(lambda);
+        white += cmf;
+        lambda += 20.0;
+    }
+    let rgb = xyz_to_rgb(xyz);
+    let white = xyz_to_rgb(white);
+    Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+}
+
+struct SpectralConstants {
+    cie_y_integral: f64,
+    // RGB of a constant unit spectrum, used to white balance the equal-energy illuminant
+    white_rgb: Color,
+}
+
+fn spectral_constants() -> &'static SpectralConstants {
+    static CONSTANTS: OnceLock<SpectralConstants> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            sum += cie_xyz(lambda);
+            lambda += 1.0;
+        }
+        SpectralConstants { cie_y_integral: sum.y(), white_rgb: xyz_to_rgb(sum / sum.y()) }
+    })
+}
+
+// Spectrum through an RGB triple of a quantity that isn't a reflectance, such as a conductor's
+// index of refraction, so Smits' conversion, which assumes values in [0,1], doesn't apply. The
+// channels are read as samples at 610, 550 and 465nm, joined linearly and held beyond them.
+pub fn unbounded_rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    const BLUE: f64 = 465.0;
+    const GREEN: f64 = 550.0;
+    const RED: f64 = 610.0;
+    if lambda <= BLUE {
+        rgb.z()
+    } else if lambda <= GREEN {
+        let t = (lambda - BLUE) / (GREEN - BLUE);
+        rgb.z() + t * (rgb.y() - rgb.z())
+    } else if lambda <= RED {
+        let t = (lambda - GREEN) / (RED - GREEN);
+        rgb.y() + t * (rgb.x() - rgb.y())
+    } else {
+        rgb.x()
+    }
+}
+
+// Smits' basis spectra over 10 bins spanning 380-720nm
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+// Evaluates Smits' RGB to spectrum conversion at a single wavelength.
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = (((lambda - 380.0) / 34.0).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let (white, cyan, magenta, yellow, red, green, blue) = (
+        SMITS_WHITE[bin],
+        SMITS_CYAN[bin],
+        SMITS_MAGENTA[bin],
+        SMITS_YELLOW[bin],
+        SMITS_RED[bin],
+        SMITS_GREEN[bin],
+        SMITS_BLUE[bin],
+    );
+
+    let value = if r <= g && r <= b {
+        r * white + if g <= b { (g - r) * cyan + (b - g) * blue } else { (b - r) * cyan + (g - b) * green }
+    } else if g <= r && g <= b {
+        g * white + if r <= b { (r - g) * magenta + (b - r) * blue } else { (b - g) * magenta + (r - b) * red }
+    } else {
+        b * white + if r <= g { (r - b) * yellow + (g - r) * green } else { (g - b) * yellow + (r - g) * red }
+    };
+    value.max(0.0)
+}
@@ -1,28 +1,65 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::onb::Onb;
+use crate::rtweekend::{Sampler, INFINITY, PI};
 use crate::ray::*;
 use crate::vec3::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    mat: Option<Rc<dyn Material>>,
+    mat: Option<Arc<dyn Material>>,
+    bbox: Aabb,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, mat: Option<Rc<dyn Material>>) -> Self {
+    pub fn new(center: Point3, radius: f64, mat: Option<Arc<dyn Material>>) -> Self {
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::new(radius, radius, radius);
         Self {
             center,
-            radius: f64::max(0.0, radius),
+            radius,
             mat,
+            bbox: Aabb::from_points(center - rvec, center + rvec),
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut Sampler) -> Vec3 {
+        let r1 = sampler.next_f64();
+        let r2 = sampler.next_f64();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
+    // Texture coordinates of a point `p` on the unit sphere: u is the angle around the y axis
+    // starting from -x, v the angle from -y up to +y, both mapped to [0,1].
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // dp/du and dp/dv for the parameterization of get_sphere_uv, given the outward unit normal.
+    // dp/du vanishes at the poles.
+    fn surface_derivatives(&self, n: &Vec3) -> (Vec3, Vec3) {
+        let sin_theta = (n.x() * n.x() + n.z() * n.z()).sqrt();
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z(), 0.0, -n.x());
+        if sin_theta < 1e-9 {
+            return (dpdu, Vec3::new(0.0, 0.0, 0.0));
+        }
+        let dpdv = PI * self.radius * Vec3::new(-n.y() * n.x() / sin_theta, sin_theta, -n.y() * n.z() / sin_theta);
+        (dpdu, dpdv)
+    }
+
+    fn intersect(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let oc = self.center - r.origin();
         let a = r.direction().length_squared();
         let half_b = oc.dot(&r.direction());
@@ -47,7 +84,47 @@ impl Hittable for Sphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(&outward_normal);
+        (rec.dpdu, rec.dpdv) = self.surface_derivatives(&outward_normal);
         rec.mat = self.mat.clone();
         true
     }
 }
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        self.intersect(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Samples the cone of directions subtended by the sphere, which only works from outside it
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        if !self.intersect(&Ray::new(*origin, *direction), Interval::new(0.001, INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let dist_squared = (self.center - *origin).length_squared();
+        if dist_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / dist_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let uvw = Onb::new(&direction);
+        uvw.transform(&Sphere::random_to_sphere(self.radius, distance_squared, sampler))
+    }
+
+    fn surface_area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
@@ -0,0 +1,64 @@
+use crate::constant_medium::ConstantMedium;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, Material};
+use crate::rtweekend::*;
+use std::sync::Arc;
+
+// Random-walk subsurface scattering, for skin, wax, marble or milk.
+//
+// The material itself is the dielectric surface of the object, and `fill` puts a scattering
+// medium inside the closed shape that uses it. Paths refract in, random walk through the medium
+// and refract back out somewhere else.
+//
+// `mean_free_path` is the average distance light travels between collisions, per channel, and
+// `albedo` the probability of scattering rather than being absorbed at each collision. Dense
+// media take many bounces, so raise the camera's max_depth to match.
+pub struct Subsurface {
+    pub albedo: Color,
+    pub mean_free_path: Color,
+    interface: Dielectric,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color, ior: f64) -> Self {
+        Self::rough(albedo, mean_free_path, ior, 0.0)
+    }
+
+    pub fn rough(albedo: Color, mean_free_path: Color, ior: f64, roughness: f64) -> Self {
+        Self { albedo, mean_free_path, interface: Dielectric::rough(ior, roughness) }
+    }
+
+    // Presets are tuned for objects about one unit across
+    pub fn marble() -> Self {
+        Self::rough(Color::new(0.999, 0.998, 0.995), Color::new(0.45, 0.38, 0.29), 1.5, 0.1)
+    }
+
+    pub fn skin() -> Self {
+        Self::rough(Color::new(0.99, 0.95, 0.90), Color::new(0.37, 0.14, 0.08), 1.4, 0.35)
+    }
+
+    // The object made of this material: `boundary`, which must be closed and use this material,
+    // together with the medium inside it.
+    pub fn fill(&self, boundary: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+        let density = |mfp: f64| if mfp > 0.0 { 1.0 / mfp } else { 1e12 };
+        let mfp = self.mean_free_path;
+        let sigma_t = Color::new(density(mfp.x()), density(mfp.y()), density(mfp.z()));
+        let medium = ConstantMedium::chromatic(boundary.clone(), sigma_t, self.albedo);
+        Arc::new(HittableList::new(vec![boundary, Arc::new(medium)]))
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut Sampler) -> bool {
+        self.interface.scatter(r_in, rec, attenuation, scattered, sampler)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        self.interface.eval(r_in, rec, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        self.interface.pdf(r_in, rec, wi)
+    }
+}
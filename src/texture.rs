@@ -0,0 +1,124 @@
+use crate::hdr_image::HdrImage;
+use crate::perlin::Perlin;
+use crate::rtweekend::*;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+
+    pub fn from_rgb(red: f64, green: f64, blue: f64) -> Self {
+        Self::new(Color::new(red, green, blue))
+    }
+
+    // Texture holding the same value in every channel, for scalar material parameters
+    pub fn scalar(value: f64) -> Self {
+        Self::from_rgb(value, value, value)
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// 3D checker pattern alternating between two textures in cubes of side `scale`.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self { inv_scale: 1.0 / scale, even, odd }
+    }
+
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(scale, Arc::new(SolidColor::new(c1)), Arc::new(SolidColor::new(c2)))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// Marble-like pattern: stripes along z whose phase is disturbed by Perlin turbulence. Higher
+// scales give narrower stripes.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, sampler: &mut Sampler) -> Self {
+        Self { noise: Perlin::new(sampler), scale }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        Color::new(0.5, 0.5, 0.5) * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin())
+    }
+}
+
+// Image looked up by surface coordinates, with v = 0 at the bottom row. Texels are bilinearly
+// filtered and wrap around at the edges.
+pub struct ImageTexture {
+    image: HdrImage,
+}
+
+impl ImageTexture {
+    pub fn new(image: HdrImage) -> Self {
+        Self { image }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(HdrImage::load(path)?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let (w, h) = (self.image.width(), self.image.height());
+        if w == 0 || h == 0 {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        let x = u.rem_euclid(1.0) * w as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * h as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let texel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(w as i64) as usize;
+            let j = (j as i64).rem_euclid(h as i64) as usize;
+            self.image.pixel(i, j)
+        };
+
+        let top = (1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1.0, y0);
+        let bottom = (1.0 - tx) * texel(x0, y0 + 1.0) + tx * texel(x0 + 1.0, y0 + 1.0);
+        (1.0 - ty) * top + ty * bottom
+    }
+}
@@ -0,0 +1,182 @@
+use crate::rtweekend::*;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = a[j][i];
+        }
+    }
+    m
+}
+
+// Gauss-Jordan elimination with partial pivoting; None for a singular matrix
+fn invert(a: &Matrix) -> Option<Matrix> {
+    let mut m = *a;
+    let mut inv = IDENTITY;
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let scale = 1.0 / m[col][col];
+        for j in 0..4 {
+            m[col][j] *= scale;
+            inv[col][j] *= scale;
+        }
+        for row in 0..4 {
+            if row != col {
+                let factor = m[row][col];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+// Affine transformation of 3D space, kept together with its inverse.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self { m: IDENTITY, inv: IDENTITY }
+    }
+
+    // Transform from a row-major matrix, or None if it can't be inverted
+    pub fn from_matrix(m: [[f64; 4]; 4]) -> Option<Self> {
+        Some(Self { m, inv: invert(&m)? })
+    }
+
+    pub fn translate(delta: Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            m[i][3] = delta[i];
+            inv[i][3] = -delta[i];
+        }
+        Self { m, inv }
+    }
+
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for (i, s) in [x, y, z].into_iter().enumerate() {
+            m[i][i] = s;
+            inv[i][i] = 1.0 / s;
+        }
+        Self { m, inv }
+    }
+
+    // Counterclockwise rotation by `degrees` around `axis`, looking down the axis
+    pub fn rotate(degrees: f64, axis: Vec3) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let mut m = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                let outer = a[i] * a[j] * (1.0 - cos);
+                let cross = match (i, j) {
+                    (0, 1) => -a[2],
+                    (0, 2) => a[1],
+                    (1, 0) => a[2],
+                    (1, 2) => -a[0],
+                    (2, 0) => -a[1],
+                    (2, 1) => a[0],
+                    _ => 0.0,
+                };
+                m[i][j] = outer + cross * sin + if i == j { cos } else { 0.0 };
+            }
+        }
+        // Rotations are orthogonal, so the inverse is the transpose
+        Self { m, inv: transpose(&m) }
+    }
+
+    // Transform that applies `self` first and then `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform { m: multiply(&next.m, &self.m), inv: multiply(&self.inv, &next.inv) }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { m: self.inv, inv: self.m }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 { Point3::new(x, y, z) } else { Point3::new(x, y, z) / w }
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Surface normals transform by the inverse transpose, which keeps them perpendicular to
+    // transformed tangents
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::new(
+            inv[0][0] * n.x() + inv[1][0] * n.y() + inv[2][0] * n.z(),
+            inv[0][1] * n.x() + inv[1][1] * n.y() + inv[2][1] * n.z(),
+            inv[0][2] * n.x() + inv[1][2] * n.y() + inv[2][2] * n.z(),
+        )
+    }
+
+    // True when the linear part is a rotation, possibly mirrored, times a uniform scale. Such
+    // transforms scale every area by the same factor, so areas and densities carry over.
+    pub fn is_similarity(&self) -> bool {
+        let m = &self.m;
+        let column = |j: usize| Vec3::new(m[0][j], m[1][j], m[2][j]);
+        let (c0, c1, c2) = (column(0), column(1), column(2));
+        let scale = c0.length_squared();
+        let tolerance = 1e-9 * scale;
+        (c1.length_squared() - scale).abs() <= tolerance
+            && (c2.length_squared() - scale).abs() <= tolerance
+            && c0.dot(&c1).abs() <= tolerance
+            && c0.dot(&c2).abs() <= tolerance
+            && c1.dot(&c2).abs() <= tolerance
+    }
+
+    // Determinant of the linear part, the factor by which volumes change
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
@@ -0,0 +1,235 @@
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::rtweekend::*;
+use crate::transform::Transform;
+use std::sync::Arc;
+
+// Vertex data shared by the triangles of a mesh. Each face lists the indices of its three
+// corners. Normals, texture coordinates and colors are optional: either empty or one per
+// position.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[usize; 3]>) -> Self {
+        Self { positions, normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), indices }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    // Moves every vertex by `t`, so the mesh can be placed without an Instance
+    pub fn transform(&mut self, t: &Transform) {
+        for p in &mut self.positions {
+            *p = t.point(p);
+        }
+        for n in &mut self.normals {
+            *n = t.normal(n).unit_vector();
+        }
+    }
+
+    // Reverses the winding of every face, turning the outward side of normal-less faces around
+    pub fn flip_winding(&mut self) {
+        for face in &mut self.indices {
+            face.swap(1, 2);
+        }
+    }
+}
+
+// One face of a TriangleMesh.
+//
+// The outward normal follows the winding, counterclockwise seen from outside, unless the mesh
+// has vertex normals, which then decide the outward side. Those normals are interpolated into
+// the shading normal.
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+    mat: Option<Arc<dyn Material>>,
+    bbox: Aabb,
+    area: f64,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<TriangleMesh>, face: usize, mat: Option<Arc<dyn Material>>) -> Self {
+        let (p0, p1, p2) = Self::corners(&mesh, face);
+
+        // Padded like Quad's box, so axis-aligned triangles still have some thickness
+        let mut bbox = Aabb::surrounding(&Aabb::from_points(p0, p1), &Aabb::from_points(p2, p2));
+        let delta = 0.0001;
+        if bbox.x.size() < delta { bbox.x = bbox.x.expand(delta); }
+        if bbox.y.size() < delta { bbox.y = bbox.y.expand(delta); }
+        if bbox.z.size() < delta { bbox.z = bbox.z.expand(delta); }
+
+        let area = 0.5 * (p1 - p0).cross(&(p2 - p0)).length();
+        Self { mesh, face, mat, bbox, area }
+    }
+
+    fn corners(mesh: &TriangleMesh, face: usize) -> (Point3, Point3, Point3) {
+        let [i0, i1, i2] = mesh.indices[face];
+        (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2])
+    }
+
+    // Unit normal from the winding, turned towards `shading` when that is nonzero
+    fn outward_normal(&self, shading: &Vec3) -> Vec3 {
+        let (p0, p1, p2) = Self::corners(&self.mesh, self.face);
+        let n = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        if n.dot(shading) < 0.0 { -n } else { n }
+    }
+
+    fn interpolated_normal(&self, b0: f64, b1: f64, b2: f64) -> Vec3 {
+        if self.mesh.normals.is_empty() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let normals = &self.mesh.normals;
+        b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]
+    }
+
+    fn interpolated_color(&self, b0: f64, b1: f64, b2: f64) -> Option<Color> {
+        if self.mesh.colors.is_empty() {
+            return None;
+        }
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let colors = &self.mesh.colors;
+        Some(b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2])
+    }
+
+    // Möller-Trumbore: solve for t and the barycentric coordinates of the hit at once
+    fn intersect(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let (p0, p1, p2) = Self::corners(&self.mesh, self.face);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = r.direction().cross(&e2);
+        let det = e1.dot(&pvec);
+        if det == 0.0 {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return false;
+        }
+        let qvec = tvec.cross(&e1);
+        let b2 = r.direction().dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false;
+        }
+        let t = e2.dot(&qvec) * inv_det;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        rec.t = t;
+        rec.p = r.at(t);
+        let shading = self.interpolated_normal(b0, b1, b2);
+        let outward = self.outward_normal(&shading);
+        rec.set_face_normal(r, outward);
+        if !shading.near_zero() {
+            let shading = shading.unit_vector();
+            rec.normal = if rec.front_face { shading } else { -shading };
+        }
+
+        let [(u0, v0), (u1, v1), (u2, v2)] = corner_uvs(&self.mesh, self.face);
+        rec.u = b0 * u0 + b1 * u1 + b2 * u2;
+        rec.v = b0 * v0 + b1 * v1 + b2 * v2;
+
+        // Solve p - p2 = (u - u2) dpdu + (v - v2) dpdv over two of the edges
+        let (du02, dv02, du12, dv12) = (u0 - u2, v0 - v2, u1 - u2, v1 - v2);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let uv_det = du02 * dv12 - dv02 * du12;
+        if uv_det.abs() < 1e-12 {
+            let uvw = Onb::new(&outward);
+            (rec.dpdu, rec.dpdv) = (uvw.u(), uvw.v());
+        } else {
+            let inv = 1.0 / uv_det;
+            rec.dpdu = (dv12 * dp02 - dv02 * dp12) * inv;
+            rec.dpdv = (du02 * dp12 - du12 * dp02) * inv;
+        }
+
+        rec.color = self.interpolated_color(b0, b1, b2);
+        rec.mat = self.mat.clone();
+        true
+    }
+}
+
+// Texture coordinates of a face's corners, (0,0), (1,0) and (1,1) when the mesh has none
+fn corner_uvs(mesh: &TriangleMesh, face: usize) -> [(f64, f64); 3] {
+    if mesh.uvs.is_empty() {
+        return [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)];
+    }
+    let [i0, i1, i2] = mesh.indices[face];
+    [mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]]
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        self.intersect(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        if !self.intersect(&Ray::new(*origin, *direction), Interval::new(0.001, INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.geometric_normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    // Uniform point on the triangle, using the square root warp of the barycentrics
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let (p0, p1, p2) = Self::corners(&self.mesh, self.face);
+        let su0 = sampler.next_f64().sqrt();
+        let b0 = 1.0 - su0;
+        let b1 = sampler.next_f64() * su0;
+        let p = b0 * p0 + b1 * p1 + (1.0 - b0 - b1) * p2;
+        p - *origin
+    }
+
+    fn surface_area(&self) -> f64 {
+        self.area
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        let third = 1.0 / 3.0;
+        (self.outward_normal(&self.interpolated_normal(third, third, third)), 1.0)
+    }
+}
+
+// All the faces of a mesh under one BVH.
+pub fn make_mesh(mesh: Arc<TriangleMesh>, mat: Option<Arc<dyn Material>>) -> BvhNode {
+    let triangles = (0..mesh.indices.len())
+        .map(|face| Arc::new(Triangle::new(mesh.clone(), face, mat.clone())) as Arc<dyn Hittable>)
+        .collect();
+    BvhNode::new(triangles)
+}
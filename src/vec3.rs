@@ -9,7 +9,7 @@ use std::ops::AddAssign;
 use std::ops::SubAssign;
 use std::ops::MulAssign;
 use std::ops::DivAssign;
-use crate::rtweekend::{random_double_range};
+use crate::sampler::Sampler;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vec3 {
@@ -44,6 +44,10 @@ impl Vec3 {
         self.dot(self)
     }
 
+    pub fn max_component(&self) -> f64 {
+        self.e[0].max(self.e[1]).max(self.e[2])
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         self.e[0].abs() < s && self.e[1].abs() < s && self.e[2].abs() < s
@@ -65,21 +69,21 @@ impl Vec3 {
         *self / self.length()
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
+    pub fn random_range(sampler: &mut Sampler, min: f64, max: f64) -> Vec3 {
         Vec3::new(
-            random_double_range(min, max),
-            random_double_range(min, max),
-            random_double_range(min, max)
+            sampler.range(min, max),
+            sampler.range(min, max),
+            sampler.range(min, max)
         )
     }
 
-    pub fn random() -> Vec3 {
-        Vec3::random_range(0.0, 1.0)
+    pub fn random(sampler: &mut Sampler) -> Vec3 {
+        Vec3::random_range(sampler, 0.0, 1.0)
     }
 
-    pub fn random_unit_vector() -> Vec3 {
+    pub fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
         loop {
-            let p = Vec3::random_range(-1.0, 1.0);
+            let p = Vec3::random_range(sampler, -1.0, 1.0);
             let length_squared = p.length_squared();
             if 1e-160 < length_squared && length_squared <= 1.0 {
                 return p / length_squared.sqrt();
@@ -87,8 +91,8 @@ impl Vec3 {
         }
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(sampler: &mut Sampler, normal: &Vec3) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit_vector(sampler);
         if on_unit_sphere.dot(normal) > 0.0 {
             on_unit_sphere
         } else {
@@ -96,6 +100,19 @@ impl Vec3 {
         }
     }
 
+    // Cosine-weighted direction on the hemisphere around +z
+    pub fn random_cosine_direction(sampler: &mut Sampler) -> Vec3 {
+        let r1 = sampler.next_f64();
+        let r2 = sampler.next_f64();
+
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
     pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
         *v - 2.0 * v.dot(n) * *n
     }
@@ -207,28 +224,3 @@ impl IndexMut<usize> for Vec3 {
         &mut self.e[i]
     }
 }
-
-fn main() {
-    let mut v = Vec3::new(1.0, 2.0, 3.0);
-    println!("Original: {} {} {}", v[0], v[1], v[2]);
-    
-    // Demonstrate mutable indexing
-    v[0] = 4.0;
-    println!("After modification: {} {} {}", v[0], v[1], v[2]);
-    
-    let neg_v = -v;
-    println!("Negated: {} {} {}", neg_v[0], neg_v[1], neg_v[2]);
-
-    let v1 = Vec3::new(1.0, 2.0, 3.0);
-    let v2 = Vec3::new(4.0, 5.0, 6.0);
-    let v3 = v1 + v2;
-    println!("Addition: {} {} {}", v3[0], v3[1], v3[2]);
-
-    let v4 = v1 * 2.0;
-    println!("Multiplication: {} {} {}", v4[0], v4[1], v4[2]);
-
-    let v5 = v1 / 2.0;
-    println!("Division: {} {} {}", v5[0], v5[1], v5[2]);
-
-    
-}
\ No newline at end of file